@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shenzhen::code::Loc;
+
+// Any line either fails to parse or parses to a `Loc` that prints back to
+// something parsing to the same `Loc`.
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    if text.contains('\n') {
+        return;
+    }
+    let Ok(loc) = text.parse::<Loc>() else {
+        return;
+    };
+    match loc.to_string().parse::<Loc>() {
+        Ok(reparsed) => assert_eq!(reparsed, loc),
+        Err(err) => panic!(
+            "{:?} printed as {:?} fails to parse: {}",
+            text,
+            loc.to_string(),
+            err
+        ),
+    }
+});
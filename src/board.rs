@@ -1,12 +1,25 @@
-use crate::device::{Attachment, Device};
+use crate::{
+    code::Pin,
+    device::Device,
+    error::{ExecError, NetError},
+    net::{DeviceId, Net, NetId, NetKind},
+};
 
-struct GlobalState {
-    seconds: u32,
-    ticks: u32,
+/// Steps a single time unit may take before we give up on the devices ever
+/// sleeping. The game has no such cap, but a chip that never sleeps would
+/// otherwise hang the emulator.
+pub const MAX_TICKS: u32 = 10_000;
+
+#[derive(Default)]
+pub struct GlobalState {
+    /// Time units elapsed since the board started.
+    pub seconds: u32,
+    /// Rounds of steps taken within the current time unit.
+    pub ticks: u32,
 }
 
 impl GlobalState {
-    fn new() -> Self {
+    pub fn new() -> Self {
         GlobalState {
             seconds: 0,
             ticks: 0,
@@ -14,13 +27,471 @@ impl GlobalState {
     }
 }
 
-struct Board {
+#[derive(Default)]
+pub struct Board {
     state: GlobalState,
     devices: Vec<Box<dyn Device>>,
-    wires: Vec<Attachment>,
+    nets: Vec<Net>,
 }
 
 impl Board {
-    pub fn advance(&mut self) {}
+    pub fn new() -> Self {
+        Board {
+            state: GlobalState::new(),
+            devices: Vec::new(),
+            nets: Vec::new(),
+        }
+    }
+
+    /// Adds a device to the board, returning its id.
+    pub fn add_device(&mut self, device: Box<dyn Device>) -> DeviceId {
+        self.devices.push(device);
+        self.devices.len() - 1
+    }
+
+    /// Adds an empty net to the board, returning its id.
+    pub fn add_net(&mut self, kind: NetKind) -> NetId {
+        self.nets.push(Net::new(kind));
+        self.nets.len() - 1
+    }
+
+    pub fn net(&self, net: NetId) -> Option<&Net> {
+        self.nets.get(net)
+    }
+
+    pub fn nets(&self) -> &[Net] {
+        &self.nets
+    }
+
+    /// Wires `pin` of `device` into `net`. Each pin can join one net, and only
+    /// one of its own kind.
+    pub fn connect(&mut self, net: NetId, device: DeviceId, pin: Pin) -> Result<(), NetError> {
+        if device >= self.devices.len() {
+            return Err(NetError::NoSuchDevice { device });
+        }
+        let kind = self
+            .nets
+            .get(net)
+            .ok_or(NetError::NoSuchNet { net })?
+            .kind();
+        if NetKind::of(pin) != kind {
+            return Err(NetError::KindMismatch { device, pin, kind });
+        }
+        if self
+            .nets
+            .iter()
+            .any(|net| net.endpoints().contains(&(device, pin)))
+        {
+            return Err(NetError::AlreadyConnected { device, pin });
+        }
+
+        let attachment = self.nets[net].probe();
+        self.devices[device]
+            .attach(pin, attachment)
+            .map_err(|_| NetError::NoSuchPin { device, pin })?;
+        self.nets[net].add_endpoint(device, pin);
+        Ok(())
+    }
+
+    pub fn state(&self) -> &GlobalState {
+        &self.state
+    }
+
+    /// Runs one time unit. Every device that can make progress takes one step
+    /// per round, and pin writes are committed once the round is over, so all
+    /// devices see the same pin values within a round. XBus transfers happen
+    /// as soon as both sides are there, in device order. Logic gates settle
+    /// after every round, so their outputs are up to date before the next
+    /// one. The unit ends once every device is sleeping or blocked.
+    pub fn advance(&mut self) -> Result<(), ExecError> {
+        self.state.ticks = 0;
+        self.settle()?;
+
+        while self.devices.iter().any(|device| device.ready()) {
+            if self.state.ticks == MAX_TICKS {
+                return Err(ExecError::NotSleeping { ticks: MAX_TICKS });
+            }
+
+            for device in &mut self.devices {
+                if device.ready() {
+                    device.step()?;
+                }
+            }
+            for device in &mut self.devices {
+                device.update_attachments();
+            }
+            self.settle()?;
+            self.state.ticks += 1;
+        }
+
+        for device in &mut self.devices {
+            device.next_time_unit();
+        }
+        self.state.seconds += 1;
+        Ok(())
+    }
+
+    /// Propagates simple I/O values through the zero-delay parts until nothing
+    /// changes. A chain of them settles within one pass per part, so anything
+    /// still changing after that is a loop that never will.
+    fn settle(&mut self) -> Result<(), ExecError> {
+        for _ in 0..=self.devices.len() {
+            let mut changed = false;
+            for device in &mut self.devices {
+                changed |= device.settle();
+            }
+            if !changed {
+                return Ok(());
+            }
+        }
+        Err(ExecError::CombinationalLoop)
+    }
+
+    /// Rewinds the board to time 0 so it can be run again, keeping its
+    /// devices, programs and wiring.
+    pub fn reset(&mut self) {
+        self.state = GlobalState::new();
+        for device in &mut self.devices {
+            device.reset();
+        }
+        for net in &self.nets {
+            net.clear();
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        device::DeviceState,
+        devices::{mc::ChipModel, memory::Memory},
+        testing::{add_chip, add_part},
+    };
+
+    #[test]
+    fn test_advance() {
+        let mut board = Board::new();
+        add_chip(&mut board, ChipModel::MC4000, "mov 1 acc\nslp 1", &[]);
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov 1 acc\nadd 1\nadd 1\nslp 1",
+            &[],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(board.state().seconds, 1);
+        assert_eq!(board.state().ticks, 4);
+    }
+
+    #[test]
+    fn test_advance_sleep() {
+        let mut board = Board::new();
+        add_chip(&mut board, ChipModel::MC4000, "add 1\nslp 3", &[]);
+
+        let mut ticks = vec![];
+        for _ in 0..5 {
+            board.advance().unwrap();
+            ticks.push(board.state().ticks);
+        }
+        assert_eq!(ticks, vec![2, 0, 0, 2, 0]);
+        assert_eq!(board.state().seconds, 5);
+    }
+
+    #[test]
+    fn test_xbus_rendezvous() {
+        let mut board = Board::new();
+        let net = board.add_net(NetKind::XBus);
+        let out = board.add_net(NetKind::XBus);
+        let probe = board.net(out).unwrap().probe();
+
+        // the reader comes first and has to wait for both writers
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov x0 acc\nmul 10\nadd x0\nmov acc x1",
+            &[(Pin::X0, net), (Pin::X1, out)],
+        );
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov 1 x0\nslp 1",
+            &[(Pin::X0, net)],
+        );
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov 2 x0\nslp 1",
+            &[(Pin::X0, net)],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(12));
+    }
+
+    #[test]
+    fn test_xbus_blocks_across_time_units() {
+        let mut board = Board::new();
+        let net = board.add_net(NetKind::XBus);
+        let out = board.add_net(NetKind::XBus);
+        let probe = board.net(out).unwrap().probe();
+
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "slp 1\nmov 7 x0",
+            &[(Pin::X0, net)],
+        );
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov x0 x1",
+            &[(Pin::X0, net), (Pin::X1, out)],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), None);
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(7));
+    }
+
+    #[test]
+    fn test_simple_io_max_of_drivers() {
+        let mut board = Board::new();
+        let net = board.add_net(NetKind::SimpleIO);
+        let out = board.add_net(NetKind::XBus);
+        let probe = board.net(net).unwrap().probe();
+        let out_probe = board.net(out).unwrap().probe();
+
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov 30 p0\nslp 2\nmov 0 p0",
+            &[(Pin::P0, net)],
+        );
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "slp 1\nmov 80 p0\nslp 9",
+            &[(Pin::P0, net)],
+        );
+        // the first read happens in the same round as the first write, so it
+        // only sees the value once that round is over
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov p0 acc\nadd p0\nmov acc x0",
+            &[(Pin::P0, net), (Pin::X0, out)],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(out_probe.read_value_from_attachment(), Some(30));
+        assert_eq!(probe.read_value_from_attachment(), Some(30));
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(80));
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(80));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut board = Board::new();
+        let net = board.add_net(NetKind::XBus);
+        let probe = board.net(net).unwrap().probe();
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "@ mov 7 x0\nslp 1",
+            &[(Pin::X0, net)],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(7));
+
+        board.reset();
+        assert_eq!(board.state().seconds, 0);
+        assert_eq!(probe.read_value_from_attachment(), None);
+        // the @ line runs again after a reset
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(7));
+    }
+
+    #[test]
+    fn test_slx() {
+        let mut board = Board::new();
+        let net = board.add_net(NetKind::XBus);
+        let out = board.add_net(NetKind::SimpleIO);
+        let probe = board.net(out).unwrap().probe();
+
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "slp 2\nmov 5 x0",
+            &[(Pin::X0, net)],
+        );
+        let reader = add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "slx x0\nmov x0 p0\nslp 1",
+            &[(Pin::X0, net), (Pin::P0, out)],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(
+            board.devices[reader].state(),
+            DeviceState::SleepXBus(Pin::X0)
+        );
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(0));
+        // slx leaves the value on the bus for the mov to pick up
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(5));
+    }
+
+    #[test]
+    fn test_xbus_chips() {
+        let mut board = Board::new();
+        let a = board.add_net(NetKind::XBus);
+        let b = board.add_net(NetKind::XBus);
+        let c = board.add_net(NetKind::XBus);
+        let out = board.add_net(NetKind::SimpleIO);
+        let probe = board.net(out).unwrap().probe();
+
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov 20 x0\nslp 1",
+            &[(Pin::X0, a)],
+        );
+        // doubles whatever arrives on x0 and hands it on through x3
+        let relay = add_chip(
+            &mut board,
+            ChipModel::MC4000X,
+            "mov x0 acc\nadd acc\nmov acc x3",
+            &[(Pin::X0, a), (Pin::X3, b)],
+        );
+        let sink = add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "mov x2 dat\nadd dat\nmov acc p1\nslx x1",
+            &[(Pin::X2, b), (Pin::X1, c), (Pin::P1, out)],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(40));
+        assert_eq!(board.devices[sink].state(), DeviceState::SleepXBus(Pin::X1));
+        assert_eq!(board.devices[relay].state(), DeviceState::Read(Pin::X0));
+    }
+
+    #[test]
+    fn test_xbus_parts_share_net() {
+        let mut board = Board::new();
+        let shared = board.add_net(NetKind::XBus);
+        let [a1, d1] = [(); 2].map(|_| board.add_net(NetKind::XBus));
+        let out = board.add_net(NetKind::SimpleIO);
+        let probe = board.net(out).unwrap().probe();
+
+        // the ROM's and the RAM's data pins share a net, and neither takes
+        // the cell the other has up as a write
+        add_part(
+            &mut board,
+            Box::new(Memory::rom(&[5])),
+            &[(Pin::X1, shared)],
+        );
+        add_part(
+            &mut board,
+            Box::new(Memory::ram()),
+            &[(Pin::X1, shared), (Pin::X2, a1), (Pin::X3, d1)],
+        );
+        // a chip's write reaches both, and only the RAM keeps it
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "mov 9 x0\nmov x1 p0\nslp 9",
+            &[(Pin::X0, shared), (Pin::X1, d1), (Pin::P0, out)],
+        );
+
+        board.advance().unwrap();
+        assert!(board.state().ticks < 10);
+        assert_eq!(probe.read_value_from_attachment(), Some(9));
+        board.advance().unwrap();
+        assert_eq!(board.state().ticks, 0);
+    }
+
+    #[test]
+    fn test_gen() {
+        let cases = [
+            ("gen p0 2 1", [100, 100, 0, 100, 100, 0]),
+            ("gen p0 1 2", [100, 0, 0, 100, 0, 0]),
+            ("gen p0 0 1", [0, 0, 0, 0, 0, 0]),
+            ("gen p0 1 0", [100, 100, 100, 100, 100, 100]),
+            ("gen p0 -1 -1\nslp 1", [0, 0, 0, 0, 0, 0]),
+        ];
+        for (code, expected) in cases {
+            let mut board = Board::new();
+            let net = board.add_net(NetKind::SimpleIO);
+            let probe = board.net(net).unwrap().probe();
+            add_chip(&mut board, ChipModel::MC4000, code, &[(Pin::P0, net)]);
+
+            let mut seen = [0; 6];
+            for value in &mut seen {
+                board.advance().unwrap();
+                *value = probe.read_value_from_attachment().unwrap();
+            }
+            assert_eq!(seen, expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_connect() {
+        let mut board = Board::new();
+        let simple = board.add_net(NetKind::SimpleIO);
+        let xbus = board.add_net(NetKind::XBus);
+        let device = add_chip(&mut board, ChipModel::MC4000, "nop", &[(Pin::P0, simple)]);
+
+        assert_eq!(board.net(simple).unwrap().endpoints(), &[(device, Pin::P0)]);
+        assert_eq!(
+            board.connect(simple, device, Pin::P0),
+            Err(NetError::AlreadyConnected {
+                device,
+                pin: Pin::P0
+            })
+        );
+        assert_eq!(
+            board.connect(xbus, device, Pin::P1),
+            Err(NetError::KindMismatch {
+                device,
+                pin: Pin::P1,
+                kind: NetKind::XBus
+            })
+        );
+        assert_eq!(
+            board.connect(xbus, device, Pin::X2),
+            Err(NetError::NoSuchPin {
+                device,
+                pin: Pin::X2
+            })
+        );
+        assert_eq!(
+            board.connect(7, device, Pin::X0),
+            Err(NetError::NoSuchNet { net: 7 })
+        );
+        assert_eq!(
+            board.connect(xbus, 7, Pin::X0),
+            Err(NetError::NoSuchDevice { device: 7 })
+        );
+        assert!(board.net(xbus).unwrap().endpoints().is_empty());
+    }
+
+    #[test]
+    fn test_advance_not_sleeping() {
+        let mut board = Board::new();
+        add_chip(&mut board, ChipModel::MC4000, "add 1", &[]);
+
+        assert_eq!(
+            board.advance(),
+            Err(ExecError::NotSleeping { ticks: MAX_TICKS })
+        );
+    }
+}
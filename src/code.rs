@@ -1,19 +1,40 @@
-use std::str::FromStr;
+use std::{collections::HashMap, fmt, str::FromStr};
 
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_till},
-    character::complete::{self, alphanumeric1},
-    combinator::{map, value},
-    multi::many_m_n,
-    sequence::{terminated, tuple},
-    Finish, IResult,
+    bytes::complete::{tag, tag_no_case, take_while1},
+    character::complete::{digit1, one_of, space0, space1},
+    combinator::{map, opt, recognize, rest, value},
+    error::{Error, ErrorKind},
+    sequence::{pair, preceded, terminated, tuple},
+    IResult,
 };
 
+use crate::{
+    error::{ParseError, Span},
+    value,
+};
+
+/// Widest line the in-game editor accepts, comments included.
+pub const MAX_LINE_LEN: usize = 19;
+
+const MNEMONICS: [&str; 16] = [
+    "nop", "mov", "jmp", "slp", "slx", "add", "sub", "mul", "not", "dgt", "dst", "teq", "tgt",
+    "tlt", "tcp", "gen",
+];
+
+/// Label names: letters, digits and underscores.
+fn identifier(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || c == '_')(input)
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Pin {
     P0,
     P1,
+    /// Only found on the DX300 and logic gates, so never lexed: no chip can
+    /// name it in code.
+    P2,
     X0,
     X1,
     X2,
@@ -21,18 +42,52 @@ pub enum Pin {
 }
 
 impl Pin {
+    pub fn is_xbus(&self) -> bool {
+        matches!(self, Pin::X0 | Pin::X1 | Pin::X2 | Pin::X3)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pin::P0 => "p0",
+            Pin::P1 => "p1",
+            Pin::P2 => "p2",
+            Pin::X0 => "x0",
+            Pin::X1 => "x1",
+            Pin::X2 => "x2",
+            Pin::X3 => "x3",
+        }
+    }
+
     pub fn lex_from_str(input: &str) -> IResult<&str, Self> {
+        alt((Pin::lex_simple_io, Pin::lex_xbus))(input)
+    }
+
+    /// Lexes only the simple I/O pins, for instructions that take nothing
+    /// else.
+    pub fn lex_simple_io(input: &str) -> IResult<&str, Self> {
+        alt((
+            value(Pin::P0, tag_no_case("p0")),
+            value(Pin::P1, tag_no_case("p1")),
+        ))(input)
+    }
+
+    /// Lexes only the XBus pins, for instructions that take nothing else.
+    pub fn lex_xbus(input: &str) -> IResult<&str, Self> {
         alt((
-            value(Pin::P0, tag("p0")),
-            value(Pin::P1, tag("p1")),
-            value(Pin::X0, tag("x0")),
-            value(Pin::X1, tag("x1")),
-            value(Pin::X2, tag("x2")),
-            value(Pin::X3, tag("x3")),
+            value(Pin::X0, tag_no_case("x0")),
+            value(Pin::X1, tag_no_case("x1")),
+            value(Pin::X2, tag_no_case("x2")),
+            value(Pin::X3, tag_no_case("x3")),
         ))(input)
     }
 }
 
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Register {
     Acc,
@@ -41,10 +96,18 @@ pub enum Register {
 }
 
 impl Register {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Register::Acc => "acc",
+            Register::Dat => "dat",
+            Register::Pin(pin) => pin.name(),
+        }
+    }
+
     pub fn lex_from_str(input: &str) -> IResult<&str, Self> {
         alt((
-            value(Register::Acc, tag("acc")),
-            value(Register::Dat, tag("dat")),
+            value(Register::Acc, tag_no_case("acc")),
+            value(Register::Dat, tag_no_case("dat")),
             |inp| {
                 let (r, x) = Pin::lex_from_str(inp)?;
                 Ok((r, Register::Pin(x)))
@@ -53,17 +116,32 @@ impl Register {
     }
 }
 
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Label(String);
 
 impl Label {
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
     pub fn lex_from_str(input: &str) -> IResult<&str, Self> {
-        let (remaining, string): (&str, &str) =
-            terminated(take_till(|c| c == ':'), tag(":"))(input)?;
+        let (remaining, string): (&str, &str) = terminated(identifier, tag(":"))(input)?;
         Ok((remaining, Label(String::from(string))))
     }
 }
 
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Condition {
     True,
@@ -81,6 +159,41 @@ impl Condition {
     }
 }
 
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Condition::True => "+",
+            Condition::False => "-",
+            Condition::Once => "@",
+        })
+    }
+}
+
+/// A number as written, sign and all, however many digits it runs to.
+fn literal(input: &str) -> IResult<&str, &str> {
+    recognize(pair(opt(one_of("+-")), digit1))(input)
+}
+
+/// The value of a `literal`, saturated at the ends of `i64`.
+fn literal_value(literal: &str) -> i64 {
+    literal.parse().unwrap_or(if literal.starts_with('-') {
+        i64::MIN
+    } else {
+        i64::MAX
+    })
+}
+
+/// Lexes an immediate. One outside `value::MIN..=value::MAX` is a failure at
+/// its first character rather than an error, so no other reading of the line
+/// gets tried.
+fn immediate(input: &str) -> IResult<&str, i16> {
+    let (rest, digits) = literal(input)?;
+    match i16::try_from(literal_value(digits)) {
+        Ok(imm) if (value::MIN..=value::MAX).contains(&imm) => Ok((rest, imm)),
+        _ => Err(nom::Err::Failure(Error::new(input, ErrorKind::Verify))),
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum RegImm {
     Reg(Register),
@@ -91,17 +204,27 @@ impl RegImm {
     pub fn lex_from_str(input: &str) -> IResult<&str, Self> {
         alt((
             map(Register::lex_from_str, RegImm::Reg),
-            map(complete::i16, RegImm::Imm),
+            map(immediate, RegImm::Imm),
         ))(input)
     }
 }
 
+impl fmt::Display for RegImm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegImm::Reg(reg) => reg.fmt(f),
+            RegImm::Imm(imm) => imm.fmt(f),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Opcode {
     Nop,
     MovReg(RegImm, Register),
     Jmp(Label),
     Slp(RegImm),
+    Slx(Pin),
     Add(RegImm),
     Sub(RegImm),
     Mul(RegImm),
@@ -112,153 +235,820 @@ pub enum Opcode {
     Tgt(RegImm, RegImm),
     Tlt(RegImm, RegImm),
     Tcp(RegImm, RegImm),
+    Gen(Pin, RegImm, RegImm),
 }
 
 impl Opcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Opcode::Nop => "nop",
+            Opcode::MovReg(..) => "mov",
+            Opcode::Jmp(_) => "jmp",
+            Opcode::Slp(_) => "slp",
+            Opcode::Slx(_) => "slx",
+            Opcode::Add(_) => "add",
+            Opcode::Sub(_) => "sub",
+            Opcode::Mul(_) => "mul",
+            Opcode::Not => "not",
+            Opcode::Dgt(_) => "dgt",
+            Opcode::Dst(..) => "dst",
+            Opcode::Teq(..) => "teq",
+            Opcode::Tgt(..) => "tgt",
+            Opcode::Tlt(..) => "tlt",
+            Opcode::Tcp(..) => "tcp",
+            Opcode::Gen(..) => "gen",
+        }
+    }
+
+    /// Every register or immediate operand, in source order.
+    pub fn operands(&self) -> Vec<RegImm> {
+        match self {
+            Opcode::Nop | Opcode::Not | Opcode::Jmp(_) => vec![],
+            Opcode::MovReg(r1, r2) => vec![*r1, RegImm::Reg(*r2)],
+            Opcode::Slp(r) | Opcode::Add(r) | Opcode::Sub(r) | Opcode::Mul(r) | Opcode::Dgt(r) => {
+                vec![*r]
+            }
+            Opcode::Dst(r1, r2)
+            | Opcode::Teq(r1, r2)
+            | Opcode::Tgt(r1, r2)
+            | Opcode::Tlt(r1, r2)
+            | Opcode::Tcp(r1, r2) => vec![*r1, *r2],
+            Opcode::Slx(p) => vec![RegImm::Reg(Register::Pin(*p))],
+            Opcode::Gen(p, r1, r2) => vec![RegImm::Reg(Register::Pin(*p)), *r1, *r2],
+        }
+    }
+
+    /// The operands an instruction reads from, in source order.
+    pub fn sources(&self) -> Vec<RegImm> {
+        match self {
+            Opcode::MovReg(r1, _) => vec![*r1],
+            // slx only waits for a value, it leaves it on the bus
+            Opcode::Slx(_) => vec![],
+            Opcode::Gen(_, r1, r2) => vec![*r1, *r2],
+            op => op.operands(),
+        }
+    }
+
     pub fn lex_from_str(input: &str) -> IResult<&str, Self> {
         alt((
-            value(Opcode::Nop, tag("nop")),
+            value(Opcode::Nop, tag_no_case("nop")),
             map(
                 tuple((
-                    tag("mov"),
-                    tag(" "),
+                    tag_no_case("mov"),
+                    space1,
                     RegImm::lex_from_str,
-                    tag(" "),
+                    space1,
                     Register::lex_from_str,
                 )),
                 |(_, _, i, _, o)| Opcode::MovReg(i, o),
             ),
             map(
                 tuple((
-                    tag("jmp"),
-                    tag(" "),
-                    map(alphanumeric1, |x| Label(String::from(x))),
+                    tag_no_case("jmp"),
+                    space1,
+                    map(identifier, |x| Label(String::from(x))),
                 )),
                 |(_, _, l)| Opcode::Jmp(l),
             ),
             map(
-                tuple((tag("slp"), tag(" "), RegImm::lex_from_str)),
+                tuple((tag_no_case("slp"), space1, RegImm::lex_from_str)),
                 |(_, _, r)| Opcode::Slp(r),
             ),
             map(
-                tuple((tag("add"), tag(" "), RegImm::lex_from_str)),
+                tuple((tag_no_case("slx"), space1, Pin::lex_xbus)),
+                |(_, _, p)| Opcode::Slx(p),
+            ),
+            map(
+                tuple((tag_no_case("add"), space1, RegImm::lex_from_str)),
                 |(_, _, r)| Opcode::Add(r),
             ),
             map(
-                tuple((tag("sub"), tag(" "), RegImm::lex_from_str)),
+                tuple((tag_no_case("sub"), space1, RegImm::lex_from_str)),
                 |(_, _, r)| Opcode::Sub(r),
             ),
             map(
-                tuple((tag("mul"), tag(" "), RegImm::lex_from_str)),
+                tuple((tag_no_case("mul"), space1, RegImm::lex_from_str)),
                 |(_, _, r)| Opcode::Mul(r),
             ),
-            value(Opcode::Not, tag("not")),
+            value(Opcode::Not, tag_no_case("not")),
             map(
-                tuple((tag("dgt"), tag(" "), RegImm::lex_from_str)),
+                tuple((tag_no_case("dgt"), space1, RegImm::lex_from_str)),
                 |(_, _, r)| Opcode::Dgt(r),
             ),
             map(
                 tuple((
-                    tag("dst"),
-                    tag(" "),
+                    tag_no_case("dst"),
+                    space1,
                     RegImm::lex_from_str,
-                    tag(" "),
+                    space1,
                     RegImm::lex_from_str,
                 )),
                 |(_, _, i, _, o)| Opcode::Dst(i, o),
             ),
             map(
                 tuple((
-                    tag("teq"),
-                    tag(" "),
+                    tag_no_case("teq"),
+                    space1,
                     RegImm::lex_from_str,
-                    tag(" "),
+                    space1,
                     RegImm::lex_from_str,
                 )),
                 |(_, _, i, _, o)| Opcode::Teq(i, o),
             ),
             map(
                 tuple((
-                    tag("tgt"),
-                    tag(" "),
+                    tag_no_case("tgt"),
+                    space1,
                     RegImm::lex_from_str,
-                    tag(" "),
+                    space1,
                     RegImm::lex_from_str,
                 )),
                 |(_, _, i, _, o)| Opcode::Tgt(i, o),
             ),
             map(
                 tuple((
-                    tag("tlt"),
-                    tag(" "),
+                    tag_no_case("tlt"),
+                    space1,
                     RegImm::lex_from_str,
-                    tag(" "),
+                    space1,
                     RegImm::lex_from_str,
                 )),
                 |(_, _, i, _, o)| Opcode::Tlt(i, o),
             ),
             map(
                 tuple((
-                    tag("tcp"),
-                    tag(" "),
+                    tag_no_case("tcp"),
+                    space1,
                     RegImm::lex_from_str,
-                    tag(" "),
+                    space1,
                     RegImm::lex_from_str,
                 )),
                 |(_, _, i, _, o)| Opcode::Tcp(i, o),
             ),
+            map(
+                tuple((
+                    tag_no_case("gen"),
+                    space1,
+                    Pin::lex_simple_io,
+                    space1,
+                    RegImm::lex_from_str,
+                    space1,
+                    RegImm::lex_from_str,
+                )),
+                |(_, _, p, _, i, _, o)| Opcode::Gen(p, i, o),
+            ),
         ))(input)
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mnemonic())?;
+        match self {
+            Opcode::MovReg(src, dst) => write!(f, " {} {}", src, dst),
+            Opcode::Jmp(label) => write!(f, " {}", label),
+            Opcode::Slx(pin) => write!(f, " {}", pin),
+            Opcode::Gen(pin, r1, r2) => write!(f, " {} {} {}", pin, r1, r2),
+            op => op.operands().iter().try_for_each(|r| write!(f, " {}", r)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Loc {
     pub cond: Option<Condition>,
     pub lab: Option<Label>,
     pub op: Option<Opcode>,
+    /// Everything after the `#`, kept as written.
+    pub comment: Option<String>,
 }
 
 impl Loc {
+    /// Lexes `[label:] [cond] [instruction] [# comment]`, with any amount of
+    /// spaces or tabs around each part.
     pub fn lex_from_str(input: &str) -> IResult<&str, Self> {
         map(
             tuple((
-                map(
-                    many_m_n(
-                        0,
-                        1,
-                        map(tuple((Label::lex_from_str, tag(" "))), |(a, _)| a),
-                    ),
-                    |x| x.first().cloned(),
-                ),
-                map(
-                    many_m_n(
-                        0,
-                        1,
-                        map(tuple((Condition::lex_from_str, tag(" "))), |(a, _)| a),
-                    ),
-                    |x| x.first().cloned(),
-                ),
-                map(many_m_n(0, 1, Opcode::lex_from_str), |x| x.first().cloned()),
+                space0,
+                opt(terminated(Label::lex_from_str, space0)),
+                opt(terminated(Condition::lex_from_str, space0)),
+                opt(terminated(Opcode::lex_from_str, space0)),
+                opt(preceded(tag("#"), rest)),
             )),
-            |(l, c, o)| Self {
+            |(_, l, c, o, comment)| Self {
                 cond: c,
                 lab: l,
                 op: o,
+                comment: comment.map(String::from),
             },
         )(input)
     }
 }
 
+impl Loc {
+    /// The line without its comment, in canonical spacing, split into the
+    /// label and condition prefix and the instruction.
+    fn columns(&self) -> (String, String, String) {
+        (
+            self.lab
+                .as_ref()
+                .map_or(String::new(), |l| format!("{}:", l)),
+            self.cond.map_or(String::new(), |c| c.to_string()),
+            self.op.as_ref().map_or(String::new(), Opcode::to_string),
+        )
+    }
+
+    /// Lays the line out with the label and condition padded to the given
+    /// widths, leaving no trailing whitespace before the comment.
+    fn layout(&self, label_width: usize, cond_width: usize) -> String {
+        let (label, cond, op) = self.columns();
+        let code = format!("{:label_width$}{:cond_width$}{}", label, cond, op);
+        let code = code.trim_end();
+        match &self.comment {
+            Some(comment) if code.is_empty() => format!("#{}", comment),
+            Some(comment) => format!("{} #{}", code, comment),
+            None => code.to_string(),
+        }
+    }
+
+    /// The line with nothing between its parts that the lexer does not need,
+    /// e.g. `a:+mov 1 acc#note`. Every line that parsed is at least this long,
+    /// so this always fits in `MAX_LINE_LEN`.
+    fn compact(&self) -> String {
+        let (label, cond, op) = self.columns();
+        let comment = self
+            .comment
+            .as_ref()
+            .map_or(String::new(), |c| format!("#{}", c));
+        [label, cond, op, comment].concat()
+    }
+}
+
+/// Separates the parts of the line with single spaces, unless that makes it
+/// too long to parse again, in which case it is printed compactly.
+impl fmt::Display for Loc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (label, cond, op) = self.columns();
+        let mut parts: Vec<String> = [label, cond, op]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect();
+        if let Some(comment) = &self.comment {
+            parts.push(format!("#{}", comment));
+        }
+        let spaced = parts.join(" ");
+        if spaced.chars().count() <= MAX_LINE_LEN {
+            f.write_str(&spaced)
+        } else {
+            f.write_str(&self.compact())
+        }
+    }
+}
+
 impl FromStr for Loc {
-    type Err = nom::error::Error<String>;
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match Loc::lex_from_str(s).finish() {
-            Ok((_remaining, loc)) => Ok(loc),
-            Err(nom::error::Error { input, code }) => Err(nom::error::Error {
-                input: input.to_string(),
-                code,
-            }),
+        parse_loc(0, s)
+    }
+}
+
+/// Lexes a whole line, which must be consumed completely, and checks it
+/// against the editor's limits.
+pub(crate) fn parse_loc(line: usize, text: &str) -> Result<Loc, ParseError> {
+    let len = text.chars().count();
+    if len > MAX_LINE_LEN {
+        return Err(ParseError::LineTooLong {
+            line,
+            len,
+            max: MAX_LINE_LEN,
+        });
+    }
+
+    let (rest, loc) = Loc::lex_from_str(text).map_err(|error| match error {
+        nom::Err::Failure(Error {
+            input,
+            code: ErrorKind::Verify,
+        }) => ParseError::ValueOutOfRange {
+            span: Span {
+                line,
+                column: text.len() - input.len(),
+            },
+            value: literal(input).map_or(0, |(_, digits)| literal_value(digits)),
+        },
+        _ => ParseError::Syntax {
+            span: Span { line, column: 0 },
+            rest: text.to_string(),
+        },
+    })?;
+
+    if !rest.is_empty() {
+        let span = Span {
+            line,
+            column: text.len() - rest.len(),
+        };
+        let mnemonic = rest.split_whitespace().next().unwrap_or_default();
+        if loc.op.is_none() && !MNEMONICS.contains(&mnemonic.to_lowercase().as_str()) {
+            return Err(ParseError::UnknownOpcode {
+                span,
+                mnemonic: mnemonic.to_string(),
+            });
+        }
+        return Err(ParseError::Syntax {
+            span,
+            rest: rest.to_string(),
+        });
+    }
+
+    Ok(loc)
+}
+
+/// A whole listing, one `Loc` per source line (blank lines included, so line
+/// indices match the in-game editor), with every label resolved.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program {
+    lines: Vec<Loc>,
+    labels: HashMap<Label, usize>,
+}
+
+impl Program {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut lines = Vec::new();
+        let mut labels = HashMap::new();
+
+        for (idx, text) in input.lines().enumerate() {
+            let loc = parse_loc(idx, text)?;
+
+            if let Some(label) = &loc.lab {
+                if labels.insert(label.clone(), idx).is_some() {
+                    return Err(ParseError::DuplicateLabel {
+                        span: Span {
+                            line: idx,
+                            column: 0,
+                        },
+                        label: label.clone(),
+                    });
+                }
+            }
+            lines.push(loc);
+        }
+
+        for (idx, (loc, text)) in lines.iter().zip(input.lines()).enumerate() {
+            if let Some(Opcode::Jmp(label)) = &loc.op {
+                if !labels.contains_key(label) {
+                    return Err(ParseError::UndefinedLabel {
+                        span: Span {
+                            line: idx,
+                            column: text.rfind(label.name()).unwrap_or(0),
+                        },
+                        label: label.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(Program { lines, labels })
+    }
+
+    pub fn lines(&self) -> &[Loc] {
+        &self.lines
+    }
+
+    pub fn labels(&self) -> &HashMap<Label, usize> {
+        &self.labels
+    }
+
+    pub fn label_index(&self, label: &Label) -> Option<usize> {
+        self.labels.get(label).copied()
+    }
+}
+
+/// Prints one line per `Loc`, with labels and conditions in their own
+/// columns so the instructions line up. A line that would no longer fit in
+/// `MAX_LINE_LEN` that way is printed as `Loc` prints it instead.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label_width = self
+            .lines
+            .iter()
+            .filter_map(|loc| loc.lab.as_ref())
+            .map(|label| label.name().len() + 2)
+            .max()
+            .unwrap_or(0);
+        let cond_width = if self.lines.iter().any(|loc| loc.cond.is_some()) {
+            2
+        } else {
+            0
+        };
+
+        // every line gets its own terminator, so trailing blank lines survive
+        // a trip through `str::lines`
+        for loc in &self.lines {
+            let line = loc.layout(label_width, cond_width);
+            if line.chars().count() <= MAX_LINE_LEN {
+                writeln!(f, "{}", line)?;
+            } else {
+                writeln!(f, "{}", loc)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_program() {
+        let program = Program::parse("loop: mov 100 p0\nslp 1\n\nmov 0 p0\njmp loop").unwrap();
+        assert_eq!(program.lines().len(), 5);
+        assert_eq!(program.lines()[2], Loc::default());
+        assert_eq!(
+            program.lines()[0].op,
+            Some(Opcode::MovReg(RegImm::Imm(100), Register::Pin(Pin::P0)))
+        );
+        assert_eq!(program.label_index(&Label(String::from("loop"))), Some(0));
+    }
+
+    #[test]
+    fn test_parse_label_only_line() {
+        let program = Program::parse("nop\nend:\njmp end").unwrap();
+        assert_eq!(program.label_index(&Label(String::from("end"))), Some(1));
+        assert_eq!(program.lines()[1].op, None);
+    }
+
+    #[test]
+    fn test_parse_duplicate_label() {
+        assert_eq!(
+            Program::parse("a: nop\na: nop"),
+            Err(ParseError::DuplicateLabel {
+                span: Span { line: 1, column: 0 },
+                label: Label(String::from("a"))
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_undefined_label() {
+        assert_eq!(
+            Program::parse("nop\njmp nowhere"),
+            Err(ParseError::UndefinedLabel {
+                span: Span { line: 1, column: 4 },
+                label: Label(String::from("nowhere"))
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_trailing_garbage() {
+        assert_eq!(
+            Program::parse("mov 1 acc\nadd 1 2"),
+            Err(ParseError::Syntax {
+                span: Span { line: 1, column: 6 },
+                rest: String::from("2")
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_whitespace_and_case() {
+        let loc: Loc = " a:\t+ MOV  X0\tAcc ".parse().unwrap();
+        assert_eq!(loc.lab, Some(Label(String::from("a"))));
+        assert_eq!(loc.cond, Some(Condition::True));
+        assert_eq!(
+            loc.op,
+            Some(Opcode::MovReg(
+                RegImm::Reg(Register::Pin(Pin::X0)),
+                Register::Acc
+            ))
+        );
+        assert_eq!(loc.comment, None);
+        assert_eq!(" \t ".parse::<Loc>().unwrap(), Loc::default());
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let program = Program::parse("# blink\nmov 100 p0 # on\nslp 1#wait\nend:# done").unwrap();
+        let lines = program.lines();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0].op, None);
+        assert_eq!(lines[0].comment.as_deref(), Some(" blink"));
+        assert_eq!(
+            lines[1].op,
+            Some(Opcode::MovReg(RegImm::Imm(100), Register::Pin(Pin::P0)))
+        );
+        assert_eq!(lines[1].comment.as_deref(), Some(" on"));
+        assert_eq!(lines[2].comment.as_deref(), Some("wait"));
+        assert_eq!(program.label_index(&Label(String::from("end"))), Some(3));
+        assert_eq!(lines[3].comment.as_deref(), Some(" done"));
+
+        // a comment is not a label, even with a colon in it
+        assert_eq!("# a: b".parse::<Loc>().unwrap().lab, None);
+    }
+
+    #[test]
+    fn test_parse_unknown_opcode() {
+        assert_eq!(
+            "+ jz 3".parse::<Loc>(),
+            Err(ParseError::UnknownOpcode {
+                span: Span { line: 0, column: 2 },
+                mnemonic: String::from("jz")
+            })
+        );
+        assert_eq!(
+            "mov 1".parse::<Loc>(),
+            Err(ParseError::Syntax {
+                span: Span { line: 0, column: 0 },
+                rest: String::from("mov 1")
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_gen() {
+        assert_eq!(
+            "gen p1 acc 3".parse::<Loc>().unwrap().op,
+            Some(Opcode::Gen(
+                Pin::P1,
+                RegImm::Reg(Register::Acc),
+                RegImm::Imm(3)
+            ))
+        );
+        assert!("gen p1 3".parse::<Loc>().is_err());
+        assert_eq!(
+            "gen x0 1 1".parse::<Loc>(),
+            Err(ParseError::Syntax {
+                span: Span { line: 0, column: 0 },
+                rest: String::from("gen x0 1 1"),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_p2() {
+        // p2 is a pin of the parts around a chip, never of the chip itself
+        for text in ["mov 1 p2", "gen p2 1 1", "add p2"] {
+            assert!(text.parse::<Loc>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_slx() {
+        assert_eq!(
+            "slx x1".parse::<Loc>().unwrap().op,
+            Some(Opcode::Slx(Pin::X1))
+        );
+        assert!("slx acc".parse::<Loc>().is_err());
+        assert_eq!(
+            "slx p0".parse::<Loc>(),
+            Err(ParseError::Syntax {
+                span: Span { line: 0, column: 0 },
+                rest: String::from("slx p0"),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_line_too_long() {
+        assert_eq!(
+            Program::parse("nop\nmov 100 acc\nlong: mov 100 p0 # x"),
+            Err(ParseError::LineTooLong {
+                line: 2,
+                len: 20,
+                max: MAX_LINE_LEN
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_value_out_of_range() {
+        assert_eq!(
+            "mov 1000 acc".parse::<Loc>(),
+            Err(ParseError::ValueOutOfRange {
+                span: Span { line: 0, column: 4 },
+                value: 1000
+            })
+        );
+        assert_eq!(
+            "mov 99999 acc".parse::<Loc>(),
+            Err(ParseError::ValueOutOfRange {
+                span: Span { line: 0, column: 4 },
+                value: 99999
+            })
+        );
+        // the column is the literal's own, wherever its digits turn up
+        assert_eq!(
+            "a1000: add 01000".parse::<Loc>(),
+            Err(ParseError::ValueOutOfRange {
+                span: Span {
+                    line: 0,
+                    column: 11
+                },
+                value: 1000
+            })
+        );
+        assert_eq!(
+            "tgt acc -99999999999".parse::<Loc>(),
+            Err(ParseError::LineTooLong {
+                line: 0,
+                len: 20,
+                max: MAX_LINE_LEN
+            })
+        );
+        assert_eq!(
+            "add -99999999999".parse::<Loc>(),
+            Err(ParseError::ValueOutOfRange {
+                span: Span { line: 0, column: 4 },
+                value: -99999999999
+            })
+        );
+        assert!("mov -999 acc".parse::<Loc>().is_ok());
+        assert!("mov +999 acc".parse::<Loc>().is_ok());
+    }
+
+    #[test]
+    fn test_display_loc() {
+        for text in [
+            "",
+            "nop",
+            "top: mov x0 acc",
+            "- jmp top",
+            "a: @ gen p1 -3 dat",
+            "dst 2 acc # tens",
+            "+ #huh",
+            "end:",
+        ] {
+            let loc: Loc = text.parse().unwrap();
+            assert_eq!(loc.to_string(), text);
+            assert_eq!(loc.to_string().parse::<Loc>().unwrap(), loc);
+        }
+        assert_eq!(
+            "A:\t+MOV  X0   Acc#x".parse::<Loc>().unwrap().to_string(),
+            "A: + mov x0 acc #x"
+        );
+
+        // lines the spacing would push past the editor width print compactly
+        for (text, printed) in [
+            ("mov 1 acc#abcdefghi", "mov 1 acc#abcdefghi"),
+            ("a:+mov x0 acc #abcd", "a:+mov x0 acc#abcd"),
+            ("a: @ gen p1 -3 dat#", "a:@gen p1 -3 dat#"),
+        ] {
+            let loc: Loc = text.parse().unwrap();
+            assert_eq!(loc.to_string(), printed);
+            assert_eq!(loc.to_string().parse::<Loc>().unwrap(), loc);
+        }
+    }
+
+    #[test]
+    fn test_display_program() {
+        let source = "# blink\nloop: mov 100 p0\nteq acc 3\n+ jmp loop\nend: slp 1 # zzz\n\n";
+        let program = Program::parse(source).unwrap();
+        let printed = program.to_string();
+        assert_eq!(
+            printed,
+            "# blink\n\
+             loop:   mov 100 p0\n\
+             \x20       teq acc 3\n\
+             \x20     + jmp loop\n\
+             end:    slp 1 # zzz\n\
+             \n"
+        );
+        assert_eq!(Program::parse(&printed).unwrap(), program);
+
+        // padding that would push a line past the editor width is dropped
+        let program = Program::parse("longname: nop\n+ mov 100 p0 # hi").unwrap();
+        let printed = program.to_string();
+        assert_eq!(printed, "longname:   nop\n+ mov 100 p0 # hi\n");
+        assert_eq!(Program::parse(&printed).unwrap(), program);
+
+        let program = Program::parse("a: nop\n+mov x0 acc #abcdef").unwrap();
+        let printed = program.to_string();
+        assert_eq!(printed, "a:   nop\n+mov x0 acc#abcdef\n");
+        assert_eq!(Program::parse(&printed).unwrap(), program);
+    }
+
+    fn pin() -> impl Strategy<Value = Pin> {
+        prop_oneof![
+            Just(Pin::P0),
+            Just(Pin::P1),
+            Just(Pin::X0),
+            Just(Pin::X1),
+            Just(Pin::X2),
+            Just(Pin::X3),
+        ]
+    }
+
+    fn simple_io_pin() -> impl Strategy<Value = Pin> {
+        prop_oneof![Just(Pin::P0), Just(Pin::P1)]
+    }
+
+    fn xbus_pin() -> impl Strategy<Value = Pin> {
+        prop_oneof![Just(Pin::X0), Just(Pin::X1), Just(Pin::X2), Just(Pin::X3),]
+    }
+
+    fn register() -> impl Strategy<Value = Register> {
+        prop_oneof![
+            Just(Register::Acc),
+            Just(Register::Dat),
+            pin().prop_map(Register::Pin),
+        ]
+    }
+
+    fn reg_imm() -> impl Strategy<Value = RegImm> {
+        prop_oneof![
+            register().prop_map(RegImm::Reg),
+            (value::MIN..=value::MAX).prop_map(RegImm::Imm),
+        ]
+    }
+
+    fn label() -> impl Strategy<Value = Label> {
+        "[a-zA-Z_][a-zA-Z0-9_]{0,5}".prop_map(Label)
+    }
+
+    fn condition() -> impl Strategy<Value = Condition> {
+        prop_oneof![
+            Just(Condition::True),
+            Just(Condition::False),
+            Just(Condition::Once),
+        ]
+    }
+
+    fn opcode() -> impl Strategy<Value = Opcode> {
+        prop_oneof![
+            Just(Opcode::Nop),
+            Just(Opcode::Not),
+            (reg_imm(), register()).prop_map(|(a, b)| Opcode::MovReg(a, b)),
+            label().prop_map(Opcode::Jmp),
+            reg_imm().prop_map(Opcode::Slp),
+            xbus_pin().prop_map(Opcode::Slx),
+            reg_imm().prop_map(Opcode::Add),
+            reg_imm().prop_map(Opcode::Sub),
+            reg_imm().prop_map(Opcode::Mul),
+            reg_imm().prop_map(Opcode::Dgt),
+            (reg_imm(), reg_imm()).prop_map(|(a, b)| Opcode::Dst(a, b)),
+            (reg_imm(), reg_imm()).prop_map(|(a, b)| Opcode::Teq(a, b)),
+            (reg_imm(), reg_imm()).prop_map(|(a, b)| Opcode::Tgt(a, b)),
+            (reg_imm(), reg_imm()).prop_map(|(a, b)| Opcode::Tlt(a, b)),
+            (reg_imm(), reg_imm()).prop_map(|(a, b)| Opcode::Tcp(a, b)),
+            (simple_io_pin(), reg_imm(), reg_imm()).prop_map(|(p, a, b)| Opcode::Gen(p, a, b)),
+        ]
+    }
+
+    fn loc() -> impl Strategy<Value = Loc> {
+        (
+            proptest::option::of(condition()),
+            proptest::option::of(label()),
+            proptest::option::of(opcode()),
+            proptest::option::of("[ -~]{0,6}"),
+        )
+            .prop_map(|(cond, lab, op, comment)| Loc {
+                cond,
+                lab,
+                op,
+                comment,
+            })
+    }
+
+    /// Printing `loc` and parsing it again has to give back the same line.
+    fn assert_reprints(loc: &Loc) -> Result<(), TestCaseError> {
+        prop_assert_eq!(loc.to_string().parse::<Loc>(), Ok(loc.clone()));
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_opcode_round_trips(op in opcode()) {
+            let loc = Loc { op: Some(op), ..Loc::default() };
+            prop_assert_eq!(loc.to_string().parse::<Loc>(), Ok(loc));
+        }
+
+        #[test]
+        fn prop_loc_round_trips(
+            // generated lines can be too long for the editor however they
+            // are printed
+            loc in loc().prop_filter("too long to parse", |loc| {
+                loc.compact().chars().count() <= MAX_LINE_LEN
+            })
+        ) {
+            assert_reprints(&loc)?;
+        }
+
+        #[test]
+        fn prop_parse_never_panics(text in "[^\\n]{0,24}") {
+            if let Ok(loc) = text.parse::<Loc>() {
+                assert_reprints(&loc)?;
+            }
+        }
+
+        #[test]
+        fn prop_program_round_trips(locs in proptest::collection::vec(loc(), 0..14)) {
+            let source: String = locs.iter().map(|loc| format!("{}\n", loc)).collect();
+            if let Ok(program) = Program::parse(&source) {
+                prop_assert_eq!(Program::parse(&program.to_string()), Ok(program));
+            }
         }
     }
 }
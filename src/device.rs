@@ -1,29 +1,97 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
+
 use crate::{
-    code::{Loc, RegImm, Register},
+    code::{Condition, Label, Loc, Pin, Program, RegImm, Register},
     devices::mc::execute_loc,
+    error::{ExecError, ParseError},
+    value,
 };
 
 pub trait Device {
-    fn step(&mut self);
+    fn step(&mut self) -> Result<(), ExecError>;
+    fn state(&self) -> DeviceState;
+    /// Whether stepping the device now would make any progress: it is
+    /// executing, or whatever it is blocked on has become available.
+    fn ready(&self) -> bool;
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError>;
+    fn update_attachments(&mut self);
+    /// Called by the board once every time unit has ended.
+    fn next_time_unit(&mut self);
+    /// Puts the device back how it was before the first time unit, keeping
+    /// its program and attachments.
+    fn reset(&mut self);
+    /// For parts without any delay: drive the outputs from the current
+    /// inputs straight away. Returns whether any output changed.
+    fn settle(&mut self) -> bool {
+        false
+    }
 }
 
 pub trait McDevice {
-    fn read_reg(&self, reg: Register) -> i16;
-    fn write_reg(&mut self, reg: Register, val: i16);
+    fn read_reg(&self, reg: Register) -> Result<i16, ExecError>;
+    fn write_reg(&mut self, reg: Register, val: i16) -> Result<(), ExecError>;
     fn set_condition(&mut self, state: CondState);
     fn get_state(&self) -> DeviceState;
-    fn sleep(&mut self, duration: u32);
+    fn set_state(&mut self, state: DeviceState);
+    fn jump(&mut self, label: &Label) -> Result<(), ExecError>;
     fn get_loc(&mut self) -> Option<Loc>;
+    /// Index of the line `get_loc` last fetched from the listing.
+    fn line(&self) -> usize;
+    /// Puts a fetched line back so the next `get_loc` returns it again.
+    fn hold(&mut self, loc: Loc);
+    fn held(&self) -> Option<&Loc>;
+    /// Number of values waiting to be read on an XBus pin, or `None` if the
+    /// chip has no such XBus pin.
+    fn xbus_available(&self, pin: Pin) -> Option<usize>;
+    /// Whether the last value written to an XBus pin has been read.
+    fn xbus_written(&self, pin: Pin) -> bool;
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError>;
     fn update_attachments(&mut self);
-    fn read_reg_or_imm(&mut self, reg_imm: RegImm) -> i16 {
+    fn reset(&mut self);
+    fn sleep(&mut self, duration: u32) {
+        self.set_state(DeviceState::Sleep(duration));
+    }
+
+    fn read_reg_or_imm(&mut self, reg_imm: RegImm) -> Result<i16, ExecError> {
         match reg_imm {
-            RegImm::Imm(imm) => imm,
+            RegImm::Imm(imm) => Ok(imm),
             RegImm::Reg(reg) => self.read_reg(reg),
         }
     }
 
-    fn read_two_regimm(&mut self, r1: RegImm, r2: RegImm) -> (i16, i16) {
-        (self.read_reg_or_imm(r1), self.read_reg_or_imm(r2))
+    fn read_two_regimm(&mut self, r1: RegImm, r2: RegImm) -> Result<(i16, i16), ExecError> {
+        Ok((self.read_reg_or_imm(r1)?, self.read_reg_or_imm(r2)?))
+    }
+
+    /// Saturates `val` to the register range before writing it.
+    fn write_clamped(&mut self, reg: Register, val: i32) -> Result<(), ExecError> {
+        self.write_reg(reg, value::clamp(val))
+    }
+
+    /// The first XBus pin `loc` reads from that does not yet have a value
+    /// waiting for every read. A line only runs once none are left, so its
+    /// reads never block halfway through.
+    fn starved_pin(&self, loc: &Loc) -> Option<Pin> {
+        let mut reads: HashMap<Pin, usize> = HashMap::new();
+        for src in loc.op.iter().flat_map(|op| op.sources()) {
+            let RegImm::Reg(Register::Pin(pin)) = src else {
+                continue;
+            };
+            if !pin.is_xbus() {
+                continue;
+            }
+            let count = reads.entry(pin).or_default();
+            *count += 1;
+            match self.xbus_available(pin) {
+                Some(available) if available < *count => return Some(pin),
+                _ => {}
+            }
+        }
+        None
     }
 }
 
@@ -31,34 +99,93 @@ pub trait McDevice {
 // since the devices are mmaped to registers [reg] -> attachment
 // after a cycle of updates, we should "apply" those updates by taking mapped registers and writing
 // to their corresponding attachments
+// the attachments themselves are handed out by the board's nets, see `Board::connect`
 
 impl<T: McDevice> Device for T {
-    fn step(&mut self) {
+    fn step(&mut self) -> Result<(), ExecError> {
         match self.get_state() {
-            DeviceState::Exec => {
-                if let Some(code) = self.get_loc() {
-                    execute_loc(code, self);
+            DeviceState::Exec | DeviceState::Read(_) => {
+                let Some(code) = self.get_loc() else {
+                    // nothing left that can run, so idle until the next time unit
+                    self.sleep(1);
+                    return Ok(());
+                };
+                if let Some(pin) = self.starved_pin(&code) {
+                    self.hold(code);
+                    self.set_state(DeviceState::Read(pin));
+                    return Ok(());
                 }
+                self.set_state(DeviceState::Exec);
+                execute_loc(&code, self).map_err(|error| ExecError::Line {
+                    line: self.line(),
+                    error: Box::new(error),
+                })
             }
-            DeviceState::Sleep(_) => {
-                todo!() // sleeping for n time units
-            }
-            DeviceState::Write => {
-                todo!() // blocking, waiting for write
+            // woken up by `next_time_unit`
+            DeviceState::Sleep(_) => Ok(()),
+            DeviceState::Write(pin) => {
+                if self.xbus_written(pin) {
+                    self.set_state(DeviceState::Exec);
+                }
+                Ok(())
             }
-            DeviceState::Read => {
-                todo!() // blocking, waiting for read
+            DeviceState::SleepXBus(pin) => {
+                if self.xbus_available(pin).unwrap_or(0) > 0 {
+                    self.set_state(DeviceState::Exec);
+                }
+                Ok(())
             }
         }
     }
+
+    fn state(&self) -> DeviceState {
+        self.get_state()
+    }
+
+    fn ready(&self) -> bool {
+        match self.get_state() {
+            DeviceState::Exec => true,
+            DeviceState::Sleep(_) => false,
+            DeviceState::Read(_) => self
+                .held()
+                .is_none_or(|code| self.starved_pin(code).is_none()),
+            DeviceState::Write(pin) => self.xbus_written(pin),
+            DeviceState::SleepXBus(pin) => self.xbus_available(pin).unwrap_or(0) > 0,
+        }
+    }
+
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError> {
+        McDevice::attach(self, pin, attachment)
+    }
+
+    fn update_attachments(&mut self) {
+        McDevice::update_attachments(self)
+    }
+
+    fn next_time_unit(&mut self) {
+        match self.get_state() {
+            DeviceState::Sleep(n) if n > 1 => self.set_state(DeviceState::Sleep(n - 1)),
+            DeviceState::Sleep(_) => self.set_state(DeviceState::Exec),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        McDevice::reset(self)
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DeviceState {
+    /// Sleeping for this many more time units, counting the current one.
     Sleep(u32),
     Exec,
-    Write,
-    Read,
+    /// Blocked until the value written to this XBus pin has been read.
+    Write(Pin),
+    /// Blocked until this XBus pin has a value to read.
+    Read(Pin),
+    /// Sleeping until this XBus pin has a value to read, see `slx`.
+    SleepXBus(Pin),
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -70,62 +197,338 @@ pub enum CondState {
 
 pub struct Code<const N: usize> {
     code: [Loc; N],
+    labels: HashMap<Label, usize>,
+    // set once an `@` line has run, so it is skipped for the rest of the program
+    once: [bool; N],
+    // a fetched line that could not run yet, returned again by the next fetch
+    held: Option<Loc>,
+    // the line last fetched, which a held line still counts as
+    line: usize,
     pc: usize,
     pub state: CondState,
 }
 
+impl<const N: usize> TryFrom<&Program> for Code<N> {
+    type Error = ParseError;
+
+    fn try_from(program: &Program) -> Result<Self, Self::Error> {
+        let lines = program.lines();
+        if lines.len() > N {
+            return Err(ParseError::TooManyLines {
+                lines: lines.len(),
+                max: N,
+            });
+        }
+
+        Ok(Code {
+            code: std::array::from_fn(|i| lines.get(i).cloned().unwrap_or_default()),
+            labels: program.labels().clone(),
+            once: [false; N],
+            held: None,
+            line: 0,
+            pc: 0,
+            state: CondState::None,
+        })
+    }
+}
+
 impl<const N: usize> Code<N> {
+    /// Fetches the next executable line and moves the pc past it, wrapping
+    /// back to the top of the listing. Lines whose condition does not hold
+    /// are skipped over.
     pub fn get_next_line(&mut self) -> Option<Loc> {
+        if let Some(line) = self.held.take() {
+            return Some(line);
+        }
+
         for _ in 0..N {
-            let line = self.code[self.pc].clone();
-
-            match line.op.clone() {
-                None => self.pc += 1,
-                Some(_) => match line.cond {
-                    None => {
-                        return Some(line);
-                    }
-                    Some(cond) => {
-                        todo!()
-                    }
-                },
+            let idx = self.pc;
+            let line = self.code[idx].clone();
+            self.pc = (self.pc + 1) % N;
+
+            if line.op.is_none() {
+                continue;
+            }
+
+            let runs = match line.cond {
+                None => true,
+                Some(Condition::True) => self.state == CondState::Plus,
+                Some(Condition::False) => self.state == CondState::Minus,
+                Some(Condition::Once) => !std::mem::replace(&mut self.once[idx], true),
+            };
+            if runs {
+                self.line = idx;
+                return Some(line);
             }
         }
 
         None
     }
+
+    pub fn hold(&mut self, line: Loc) {
+        self.held = Some(line);
+    }
+
+    pub fn held(&self) -> Option<&Loc> {
+        self.held.as_ref()
+    }
+
+    /// Rewinds to the top of the listing and forgets everything learned while
+    /// running: `@` lines run again and the last test result is cleared.
+    pub fn reset(&mut self) {
+        self.once = [false; N];
+        self.held = None;
+        self.line = 0;
+        self.pc = 0;
+        self.state = CondState::None;
+    }
+
+    /// Index of the line last fetched.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Index of the line the next fetch starts looking from.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn jump(&mut self, label: &Label) -> Result<(), ExecError> {
+        self.pc = *self
+            .labels
+            .get(label)
+            .ok_or_else(|| ExecError::UndefinedLabel {
+                label: label.clone(),
+            })?;
+        Ok(())
+    }
 }
 
-#[derive(Copy, Clone)]
+/// A simple I/O net. Every pin connected to it drives a value, and the net
+/// reads as the largest of them.
+#[derive(Default)]
 pub struct SimpleIOWire {
-    value: i16,
+    drivers: Vec<i16>,
 }
 
 impl SimpleIOWire {
-    pub fn push_update_with(&mut self, data: i16) {
-        todo!();
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(SimpleIOWire::default()))
+    }
+
+    pub fn value(&self) -> i16 {
+        self.drivers.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Drops every driver back to 0, keeping the pins connected.
+    pub fn clear(&mut self) {
+        self.drivers.fill(0);
+    }
+}
+
+/// One device pin's connection to a `SimpleIOWire`.
+#[derive(Clone)]
+pub struct SimpleIOPin {
+    wire: Rc<RefCell<SimpleIOWire>>,
+    driver: usize,
+}
+
+impl SimpleIOPin {
+    pub fn connect(wire: &Rc<RefCell<SimpleIOWire>>) -> Self {
+        let mut inner = wire.borrow_mut();
+        inner.drivers.push(0);
+        SimpleIOPin {
+            wire: wire.clone(),
+            driver: inner.drivers.len() - 1,
+        }
+    }
+
+    pub fn push_update_with(&self, data: i16) {
+        self.wire.borrow_mut().drivers[self.driver] = value::clamp_simple_io(data.into());
     }
 
     pub fn read_from(&self) -> i16 {
-        todo!();
+        self.wire.borrow().value()
     }
 }
 
-#[derive(Copy, Clone)]
+/// A value waiting on an `XBusWire`.
+struct Pending {
+    from: usize,
+    data: i16,
+    // put up by a part for as long as nobody reads it, rather than written
+    // by a chip that waits for it to be read
+    offered: bool,
+    // the listening parts that have taken it so far
+    seen: Vec<usize>,
+}
+
+/// An XBus net. Values queue up in the order they were made and each one is
+/// handed to exactly one chip reading the net, oldest first.
+///
+/// Parts listening on the net take only the values chips wrote, and every
+/// one of them gets each such value. A written value stays on the net until
+/// a chip reads it or every listening part has taken it. Values a part
+/// offers only ever go to chips, so parts sharing a net never feed each
+/// other.
+#[derive(Default)]
 pub struct XBusWire {
-    value: Option<i16>,
+    pending: VecDeque<Pending>,
+    endpoints: usize,
+    listeners: Vec<usize>,
+}
+
+impl XBusWire {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(XBusWire::default()))
+    }
+
+    /// Throws away every value still waiting to be read.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    fn push(&mut self, from: usize, data: i16, offered: bool) {
+        self.pending.push_back(Pending {
+            from,
+            data: value::clamp(data.into()),
+            offered,
+            seen: Vec::new(),
+        });
+    }
+}
+
+/// One device pin's connection to an `XBusWire`. A pin never reads back its
+/// own writes.
+#[derive(Clone)]
+pub struct XBusPin {
+    wire: Rc<RefCell<XBusWire>>,
+    endpoint: usize,
 }
 
+impl XBusPin {
+    pub fn connect(wire: &Rc<RefCell<XBusWire>>) -> Self {
+        let mut inner = wire.borrow_mut();
+        inner.endpoints += 1;
+        XBusPin {
+            wire: wire.clone(),
+            endpoint: inner.endpoints - 1,
+        }
+    }
+
+    pub fn write(&self, data: i16) {
+        self.wire.borrow_mut().push(self.endpoint, data, false);
+    }
+
+    /// Puts `data` up for a chip to read in place of whatever this pin
+    /// offered before.
+    pub fn offer(&self, data: i16) {
+        self.withdraw();
+        self.wire.borrow_mut().push(self.endpoint, data, true);
+    }
+
+    /// Whether every value this pin wrote or offered has been read.
+    pub fn is_written(&self) -> bool {
+        !self
+            .wire
+            .borrow()
+            .pending
+            .iter()
+            .any(|pending| pending.from == self.endpoint)
+    }
+
+    pub fn available(&self) -> usize {
+        self.wire
+            .borrow()
+            .pending
+            .iter()
+            .filter(|pending| pending.from != self.endpoint)
+            .count()
+    }
+
+    pub fn peek(&self) -> Option<i16> {
+        self.wire
+            .borrow()
+            .pending
+            .iter()
+            .find(|pending| pending.from != self.endpoint)
+            .map(|pending| pending.data)
+    }
+
+    /// Takes back every value this pin wrote or offered that has not been
+    /// read yet.
+    pub fn withdraw(&self) {
+        self.wire
+            .borrow_mut()
+            .pending
+            .retain(|pending| pending.from != self.endpoint);
+    }
+
+    pub fn read(&self) -> Option<i16> {
+        let mut wire = self.wire.borrow_mut();
+        let idx = wire
+            .pending
+            .iter()
+            .position(|pending| pending.from != self.endpoint)?;
+        wire.pending.remove(idx).map(|pending| pending.data)
+    }
+
+    /// Makes this pin a part's, which takes values through `receive` and
+    /// never blocks a chip writing to it.
+    pub fn listen(&self) {
+        let mut wire = self.wire.borrow_mut();
+        if !wire.listeners.contains(&self.endpoint) {
+            wire.listeners.push(self.endpoint);
+        }
+    }
+
+    fn receivable(&self, pending: &Pending) -> bool {
+        !pending.offered && pending.from != self.endpoint && !pending.seen.contains(&self.endpoint)
+    }
+
+    /// Number of written values this listening pin has not taken yet.
+    pub fn incoming(&self) -> usize {
+        self.wire
+            .borrow()
+            .pending
+            .iter()
+            .filter(|pending| self.receivable(pending))
+            .count()
+    }
+
+    /// Takes the oldest written value this listening pin has not taken yet.
+    pub fn receive(&self) -> Option<i16> {
+        let mut wire = self.wire.borrow_mut();
+        let idx = wire
+            .pending
+            .iter()
+            .position(|pending| self.receivable(pending))?;
+        let wire = &mut *wire;
+        let pending = &mut wire.pending[idx];
+        pending.seen.push(self.endpoint);
+        let data = pending.data;
+        let done = wire
+            .listeners
+            .iter()
+            .all(|&listener| listener == pending.from || pending.seen.contains(&listener));
+        if done {
+            wire.pending.remove(idx);
+        }
+        Some(data)
+    }
+}
+
+#[derive(Clone)]
 pub enum Attachment {
-    SimpleIO(SimpleIOWire),
-    XBus(XBusWire),
+    SimpleIO(SimpleIOPin),
+    XBus(XBusPin),
 }
 
 impl Attachment {
-    fn read_value_from_attachment(&self) -> Option<i16> {
+    pub fn read_value_from_attachment(&self) -> Option<i16> {
         match self {
-            Attachment::SimpleIO(x) => Some(x.value),
-            Attachment::XBus(x) => x.value,
+            Attachment::SimpleIO(x) => Some(x.read_from()),
+            Attachment::XBus(x) => x.peek(),
         }
     }
 }
@@ -133,156 +536,243 @@ impl Attachment {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::devices::mc::MC4000;
+
+    #[test]
+    fn test_code_from_program() {
+        let program = Program::parse("mov 1 acc\nadd 2").unwrap();
+        let code = Code::<9>::try_from(&program).unwrap();
+        assert_eq!(code.code[0], program.lines()[0]);
+        assert_eq!(code.code[1], program.lines()[1]);
+        assert_eq!(code.code[2], Loc::default());
+
+        assert_eq!(
+            Code::<1>::try_from(&program).err(),
+            Some(ParseError::TooManyLines { lines: 2, max: 1 })
+        );
+    }
+
+    #[test]
+    fn test_code_wraps_and_jumps() {
+        let program = Program::parse("top: mov 1 acc\n\nadd 2\nend: nop").unwrap();
+        let mut code = Code::<5>::try_from(&program).unwrap();
+        assert_eq!(code.get_next_line(), Some(program.lines()[0].clone()));
+        assert_eq!(code.get_next_line(), Some(program.lines()[2].clone()));
+        assert_eq!(code.line(), 2);
+        assert_eq!(code.get_next_line(), Some(program.lines()[3].clone()));
+        assert_eq!(code.get_next_line(), Some(program.lines()[0].clone()));
+        assert_eq!(code.line(), 0);
 
-    //     #[test]
-    //     fn test_regimm_to_imm() {
-    //         let mut device = MC4000 {
-    //             acc: 0,
-    //             state: DeviceState::Exec,
-    //             pins: [None, None, None, None],
-    //             code: Code {
-    //                 code: [(); 9].map(|_| Loc {
-    //                     op: None,
-    //                     lab: None,
-    //                     cond: None,
-    //                 }),
-    //                 pc: 0,
-    //                 state: CondState::None,
-    //             },
-    //         };
-    //         device.set_acc(5);
-    //         assert_eq!(regimm_to_imm(&mut device, RegImm::Imm(3)), 3);
-    //         assert_eq!(regimm_to_imm(&mut device, RegImm::Reg(Register::Acc)), 5);
-    //     }
-    //
-    //     #[test]
-    //     fn test_read_reg() {
-    //         let siowire = Rc::new(RefCell::new(SimpleIOWire { value: 4 }));
-    //         let xbwire = Rc::new(RefCell::new(XBusWire {
-    //             value: Some(7),
-    //             timeout: 0,
-    //         }));
-    //         let mut device = MC4000 {
-    //             acc: 0,
-    //             state: DeviceState::Exec,
-    //             pins: [Some(siowire), None, Some(xbwire), None],
-    //             code: Code {
-    //                 code: [(); 9].map(|_| Loc {
-    //                     op: None,
-    //                     lab: None,
-    //                     cond: None,
-    //                 }),
-    //                 pc: 0,
-    //                 state: CondState::None,
-    //             },
-    //         };
-    //         device.set_acc(5);
-    //         assert_eq!(read_reg(&mut device, Register::Acc), 5);
-    //         assert_eq!(read_reg(&mut device, Register::Pin(Pin::P0)), 4);
-    //         assert_eq!(read_reg(&mut device, Register::Pin(Pin::X0)), 7);
-    //     }
-    //
-    //     #[test]
-    //     fn test_write_reg() {
-    //         let siowire = Rc::new(RefCell::new(SimpleIOWire { value: 4 }));
-    //         let xbwire = Rc::new(RefCell::new(XBusWire {
-    //             value: Some(7),
-    //             timeout: 0,
-    //         }));
-    //         let mut device = MC4000 {
-    //             acc: 0,
-    //             state: DeviceState::Exec,
-    //             pins: [Some(siowire.clone()), None, Some(xbwire.clone()), None],
-    //             code: Code {
-    //                 code: [(); 9].map(|_| Loc {
-    //                     op: None,
-    //                     lab: None,
-    //                     cond: None,
-    //                 }),
-    //                 pc: 0,
-    //                 state: CondState::None,
-    //             },
-    //         };
-    //         write_reg(&mut device, Register::Acc, 5);
-    //         write_reg(&mut device, Register::Pin(Pin::P0), 5);
-    //         write_reg(&mut device, Register::Pin(Pin::X0), 5);
-    //         assert_eq!(device.get_acc(), Some(5));
-    //         assert_eq!(siowire.borrow_mut().get(), 5);
-    //         assert_eq!(xbwire.borrow_mut().get(), 5);
-    //     }
-    //
-    //     // opcode tests
-    //     #[test]
-    //     fn test_nop() {
-    //         let mut device = MC4000 {
-    //             acc: 0,
-    //             state: DeviceState::Exec,
-    //             pins: [None, None, None, None],
-    //             code: Code {
-    //                 code: [(); 9].map(|_| Loc {
-    //                     op: Some(Opcode::Nop),
-    //                     lab: None,
-    //                     cond: None,
-    //                 }),
-    //                 pc: 0,
-    //                 state: CondState::None,
-    //             },
-    //         };
-    //         exec(device.code.step().unwrap(), &mut device);
-    //         assert_eq!(device.code.pc, 1);
-    //     }
-    //
-    //     #[test]
-    //     fn test_movreg() {
-    //         let siowire = Rc::new(RefCell::new(SimpleIOWire { value: 4 }));
-    //         let mut device = MC4000 {
-    //             acc: 0,
-    //             state: DeviceState::Exec,
-    //             pins: [Some(siowire.clone()), None, None, None],
-    //             code: Code {
-    //                 code: [0, 1, 2, 3, 4, 5, 6, 7, 8].map(|i| Loc {
-    //                     op: Some(Opcode::MovReg(RegImm::Imm(i), Register::Acc)),
-    //                     lab: None,
-    //                     cond: None,
-    //                 }),
-    //                 pc: 0,
-    //                 state: CondState::None,
-    //             },
-    //         };
-    //         exec(device.code.step().unwrap(), &mut device);
-    //         assert_eq!(device.code.pc, 1);
-    //         assert_eq!(device.get_acc(), Some(0));
-    //
-    //         exec(device.code.step().unwrap(), &mut device);
-    //         assert_eq!(device.code.pc, 2);
-    //         assert_eq!(device.get_acc(), Some(1));
-    //
-    //         exec(device.code.step().unwrap(), &mut device);
-    //         assert_eq!(device.code.pc, 3);
-    //         assert_eq!(device.get_acc(), Some(2));
-    //
-    //         exec(device.code.step().unwrap(), &mut device);
-    //         assert_eq!(device.code.pc, 4);
-    //         assert_eq!(device.get_acc(), Some(3));
-    //
-    //         exec(device.code.step().unwrap(), &mut device);
-    //         assert_eq!(device.code.pc, 5);
-    //         assert_eq!(device.get_acc(), Some(4));
-    //
-    //         exec(device.code.step().unwrap(), &mut device);
-    //         assert_eq!(device.code.pc, 6);
-    //         assert_eq!(device.get_acc(), Some(5));
-    //
-    //         exec(device.code.step().unwrap(), &mut device);
-    //         assert_eq!(device.code.pc, 7);
-    //         assert_eq!(device.get_acc(), Some(6));
-    //
-    //         exec(device.code.step().unwrap(), &mut device);
-    //         assert_eq!(device.code.pc, 8);
-    //         assert_eq!(device.get_acc(), Some(7));
-    //
-    //         exec(device.code.step().unwrap(), &mut device);
-    //         assert_eq!(device.code.pc, 0);
-    //         assert_eq!(device.get_acc(), Some(8));
-    //     }
+        code.jump(&program.lines()[3].lab.clone().unwrap()).unwrap();
+        assert_eq!(code.get_next_line(), Some(program.lines()[3].clone()));
+    }
+
+    #[test]
+    fn test_code_conditions() {
+        let program = Program::parse("@ mov 1 acc\n+ add 1\n- sub 1\nnop").unwrap();
+        let mut code = Code::<4>::try_from(&program).unwrap();
+        let lines = program.lines();
+
+        // no test has run yet, so both + and - lines are skipped
+        assert_eq!(code.get_next_line(), Some(lines[0].clone()));
+        assert_eq!(code.get_next_line(), Some(lines[3].clone()));
+        // @ only runs the first time around
+        assert_eq!(code.get_next_line(), Some(lines[3].clone()));
+
+        code.state = CondState::Plus;
+        assert_eq!(code.get_next_line(), Some(lines[1].clone()));
+        assert_eq!(code.get_next_line(), Some(lines[3].clone()));
+
+        code.state = CondState::Minus;
+        assert_eq!(code.get_next_line(), Some(lines[2].clone()));
+        assert_eq!(code.get_next_line(), Some(lines[3].clone()));
+    }
+
+    #[test]
+    fn test_code_reset() {
+        let program = Program::parse("@ mov 1 acc\ntop: teq acc 1\n+ jmp top").unwrap();
+        let mut code = Code::<3>::try_from(&program).unwrap();
+        let lines = program.lines();
+
+        assert_eq!(code.get_next_line(), Some(lines[0].clone()));
+        assert_eq!(code.get_next_line(), Some(lines[1].clone()));
+        code.state = CondState::Plus;
+        code.hold(lines[2].clone());
+        code.reset();
+
+        assert_eq!(code.pc(), 0);
+        assert!(code.held().is_none());
+        assert!(code.state == CondState::None);
+        assert_eq!(code.get_next_line(), Some(lines[0].clone()));
+        assert_eq!(code.get_next_line(), Some(lines[1].clone()));
+        assert_eq!(code.get_next_line(), Some(lines[1].clone()));
+    }
+
+    #[test]
+    fn test_xbus_pin() {
+        let wire = XBusWire::new();
+        let a = XBusPin::connect(&wire);
+        let b = XBusPin::connect(&wire);
+        let c = XBusPin::connect(&wire);
+
+        a.write(1);
+        c.write(2);
+        assert!(!a.is_written());
+        assert_eq!(a.available(), 1);
+        assert_eq!(b.available(), 2);
+
+        assert_eq!(b.read(), Some(1));
+        assert!(a.is_written());
+        assert_eq!(c.read(), None);
+        assert_eq!(a.read(), Some(2));
+        assert!(c.is_written());
+        assert_eq!(b.read(), None);
+
+        a.write(3);
+        a.write(4);
+        b.write(5);
+        a.withdraw();
+        assert!(a.is_written());
+        assert_eq!(c.read(), Some(5));
+    }
+
+    #[test]
+    fn test_xbus_listeners() {
+        let wire = XBusWire::new();
+        let chip = XBusPin::connect(&wire);
+        let [a, b] = [(); 2].map(|_| XBusPin::connect(&wire));
+        a.listen();
+        b.listen();
+
+        // offers only go to chips, and each one replaces the last
+        a.offer(1);
+        b.offer(2);
+        a.offer(3);
+        assert_eq!(b.incoming(), 0);
+        assert_eq!(b.receive(), None);
+        assert_eq!(chip.available(), 2);
+        assert_eq!(chip.read(), Some(2));
+        assert!(b.is_written());
+
+        // a write reaches every listener and is gone once all have it
+        chip.write(4);
+        assert_eq!(a.receive(), Some(4));
+        assert_eq!(a.receive(), None);
+        assert!(!chip.is_written());
+        assert_eq!(b.incoming(), 1);
+        assert_eq!(b.receive(), Some(4));
+        assert!(chip.is_written());
+        assert_eq!(chip.read(), Some(3));
+    }
+
+    #[test]
+    fn test_simple_io_pin() {
+        let wire = SimpleIOWire::new();
+        let a = SimpleIOPin::connect(&wire);
+        let b = SimpleIOPin::connect(&wire);
+        assert_eq!(a.read_from(), 0);
+
+        a.push_update_with(30);
+        b.push_update_with(70);
+        assert_eq!(a.read_from(), 70);
+        b.push_update_with(0);
+        assert_eq!(b.read_from(), 30);
+
+        a.push_update_with(-50);
+        assert_eq!(a.read_from(), 0);
+        a.push_update_with(999);
+        assert_eq!(b.read_from(), 100);
+    }
+
+    #[test]
+    fn test_code_all_lines_skipped() {
+        let program = Program::parse("+ nop\n- nop").unwrap();
+        let mut code = Code::<2>::try_from(&program).unwrap();
+        assert_eq!(code.get_next_line(), None);
+    }
+
+    #[test]
+    fn test_read_reg_or_imm() {
+        let program = Program::parse("mov 5 acc").unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+        device.step().unwrap();
+        assert_eq!(device.read_reg_or_imm(RegImm::Imm(3)), Ok(3));
+        assert_eq!(device.read_reg_or_imm(RegImm::Reg(Register::Acc)), Ok(5));
+    }
+
+    #[test]
+    fn test_read_reg() {
+        let program = Program::parse("mov 5 acc").unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+        let sio = SimpleIOWire::new();
+        let xbus = XBusWire::new();
+        McDevice::attach(
+            &mut device,
+            Pin::P0,
+            Attachment::SimpleIO(SimpleIOPin::connect(&sio)),
+        )
+        .unwrap();
+        McDevice::attach(
+            &mut device,
+            Pin::X0,
+            Attachment::XBus(XBusPin::connect(&xbus)),
+        )
+        .unwrap();
+        SimpleIOPin::connect(&sio).push_update_with(4);
+        XBusPin::connect(&xbus).write(7);
+
+        device.step().unwrap();
+        assert_eq!(device.read_reg(Register::Acc), Ok(5));
+        assert_eq!(device.read_reg(Register::Pin(Pin::P0)), Ok(4));
+        assert_eq!(device.read_reg(Register::Pin(Pin::X0)), Ok(7));
+    }
+
+    #[test]
+    fn test_write_reg() {
+        let program = Program::parse("nop").unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+        let sio = SimpleIOWire::new();
+        let xbus = XBusWire::new();
+        McDevice::attach(
+            &mut device,
+            Pin::P0,
+            Attachment::SimpleIO(SimpleIOPin::connect(&sio)),
+        )
+        .unwrap();
+        McDevice::attach(
+            &mut device,
+            Pin::X0,
+            Attachment::XBus(XBusPin::connect(&xbus)),
+        )
+        .unwrap();
+        let (sio, xbus) = (SimpleIOPin::connect(&sio), XBusPin::connect(&xbus));
+
+        device.write_reg(Register::Acc, 5).unwrap();
+        device.write_reg(Register::Pin(Pin::P0), 5).unwrap();
+        device.write_reg(Register::Pin(Pin::X0), 5).unwrap();
+        assert_eq!(device.read_reg(Register::Acc), Ok(5));
+        // simple I/O writes only land once the attachments are updated
+        assert_eq!(sio.read_from(), 0);
+        McDevice::update_attachments(&mut device);
+        assert_eq!(sio.read_from(), 5);
+        assert_eq!(xbus.read(), Some(5));
+        assert_eq!(device.get_state(), DeviceState::Write(Pin::X0));
+    }
+
+    #[test]
+    fn test_nop_and_mov() {
+        let movs: String = (1..9).map(|i| format!("mov {} acc\n", i)).collect();
+        let program = Program::parse(&format!("nop\n{}", movs)).unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+
+        device.step().unwrap();
+        assert_eq!(device.read_reg(Register::Acc), Ok(0));
+        for acc in 1..9 {
+            device.step().unwrap();
+            assert_eq!(device.read_reg(Register::Acc), Ok(acc));
+        }
+        // back round to the nop, which leaves acc alone
+        device.step().unwrap();
+        assert_eq!(device.read_reg(Register::Acc), Ok(8));
+    }
 }
@@ -0,0 +1,416 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::{
+    code::{Pin, Pin::*, Register},
+    device::{Attachment, Device, DeviceState, XBusPin},
+    error::ExecError,
+};
+
+/// Segments lit for each digit, as `[top, upper left, upper right, middle,
+/// lower left, lower right, bottom]`.
+const SEGMENTS: [[bool; 7]; 10] = [
+    [true, true, true, false, true, true, true],
+    [false, false, true, false, false, true, false],
+    [true, false, true, true, true, false, true],
+    [true, false, true, true, false, true, true],
+    [false, true, true, true, false, true, false],
+    [true, true, false, true, false, true, true],
+    [true, true, false, true, true, true, true],
+    [true, false, true, false, false, true, false],
+    [true, true, true, true, true, true, true],
+    [true, true, true, true, false, true, true],
+];
+
+/// Takes every value chips wrote to `pin` so far.
+fn drain(pin: &Option<XBusPin>) -> Vec<i16> {
+    let mut values = Vec::new();
+    if let Some(pin) = pin {
+        while let Some(val) = pin.receive() {
+            values.push(val);
+        }
+    }
+    values
+}
+
+fn attach_x0(
+    slot: &mut Option<XBusPin>,
+    pin: Pin,
+    attachment: Attachment,
+) -> Result<(), ExecError> {
+    match (pin, attachment) {
+        (X0, Attachment::XBus(x)) => {
+            x.listen();
+            *slot = Some(x);
+        }
+        _ => {
+            return Err(ExecError::InvalidRegister {
+                register: Register::Pin(pin),
+            })
+        }
+    }
+    Ok(())
+}
+
+/// What a display is showing, as a grid of characters. Displays hand out a
+/// shared handle to theirs, so it can still be watched once the display
+/// belongs to a board.
+pub struct Framebuffer {
+    width: usize,
+    cells: Vec<char>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Framebuffer {
+            width,
+            cells: vec![' '; width * height],
+        }))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.cells.len().checked_div(self.width).unwrap_or(0)
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> Option<char> {
+        if x >= self.width {
+            return None;
+        }
+        self.cells.get(y * self.width + x).copied()
+    }
+
+    /// The contents, one string per row.
+    pub fn rows(&self) -> Vec<String> {
+        self.cells
+            .chunks(self.width.max(1))
+            .map(|row| row.iter().collect())
+            .collect()
+    }
+
+    fn clear(&mut self) {
+        self.cells.fill(' ');
+    }
+}
+
+/// Draws every row on its own line.
+impl fmt::Display for Framebuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.rows()
+            .iter()
+            .try_for_each(|row| writeln!(f, "{}", row))
+    }
+}
+
+/// The DT2415 seven-segment digit display, written to over `x0`. A value
+/// from 0 to 9 shows that digit, anything else blanks the display. It draws
+/// the digit three characters wide and three lines high.
+pub struct DT2415 {
+    xbus: Option<XBusPin>,
+    digit: Option<u8>,
+    framebuffer: Rc<RefCell<Framebuffer>>,
+}
+
+impl DT2415 {
+    pub fn new() -> Self {
+        DT2415 {
+            xbus: None,
+            digit: None,
+            framebuffer: Framebuffer::new(3, 3),
+        }
+    }
+
+    /// The digit on show, if any.
+    pub fn digit(&self) -> Option<u8> {
+        self.digit
+    }
+
+    /// Which segments are lit, see `SEGMENTS` for the order.
+    pub fn segments(&self) -> [bool; 7] {
+        self.digit
+            .map_or([false; 7], |digit| SEGMENTS[usize::from(digit)])
+    }
+
+    pub fn framebuffer(&self) -> Rc<RefCell<Framebuffer>> {
+        self.framebuffer.clone()
+    }
+
+    fn draw(&self) {
+        let [top, upper_left, upper_right, middle, lower_left, lower_right, bottom] =
+            self.segments();
+        let lit = |on: bool, c: char| if on { c } else { ' ' };
+        self.framebuffer.borrow_mut().cells = vec![
+            ' ',
+            lit(top, '_'),
+            ' ',
+            lit(upper_left, '|'),
+            lit(middle, '_'),
+            lit(upper_right, '|'),
+            lit(lower_left, '|'),
+            lit(bottom, '_'),
+            lit(lower_right, '|'),
+        ];
+    }
+}
+
+impl Default for DT2415 {
+    fn default() -> Self {
+        DT2415::new()
+    }
+}
+
+impl Device for DT2415 {
+    fn step(&mut self) -> Result<(), ExecError> {
+        for val in drain(&self.xbus) {
+            self.digit = u8::try_from(val).ok().filter(|digit| *digit <= 9);
+        }
+        self.draw();
+        Ok(())
+    }
+
+    fn state(&self) -> DeviceState {
+        DeviceState::Exec
+    }
+
+    fn ready(&self) -> bool {
+        self.xbus.as_ref().is_some_and(|x| x.incoming() > 0)
+    }
+
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError> {
+        attach_x0(&mut self.xbus, pin, attachment)
+    }
+
+    fn update_attachments(&mut self) {}
+
+    fn next_time_unit(&mut self) {}
+
+    fn reset(&mut self) {
+        self.digit = None;
+        self.framebuffer.borrow_mut().clear();
+    }
+}
+
+/// Value that clears an `Lcd` and sends its cursor home.
+pub const LCD_CLEAR: i16 = -999;
+/// Values from here up move an `Lcd`'s cursor to cell `value - LCD_MOVE`.
+pub const LCD_MOVE: i16 = 100;
+
+/// A character LCD, written to over `x0` one value at a time. The screens
+/// differ from puzzle to puzzle; this one takes:
+///
+/// - `LCD_CLEAR` to blank every cell and send the cursor to the top left,
+/// - `LCD_MOVE` plus a cell index, counting along each row, to move the
+///   cursor there,
+/// - a glyph code to draw at the cursor and move it on by one, wrapping
+///   around: 0 for a space, 1 to 26 for `A` to `Z` and 27 to 36 for `0` to
+///   `9`. Other codes draw `?`.
+pub struct Lcd {
+    xbus: Option<XBusPin>,
+    framebuffer: Rc<RefCell<Framebuffer>>,
+    cursor: usize,
+}
+
+impl Lcd {
+    pub fn new(width: usize, height: usize) -> Self {
+        Lcd {
+            xbus: None,
+            framebuffer: Framebuffer::new(width, height),
+            cursor: 0,
+        }
+    }
+
+    pub fn framebuffer(&self) -> Rc<RefCell<Framebuffer>> {
+        self.framebuffer.clone()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    fn glyph(code: i16) -> char {
+        match code {
+            0 => ' ',
+            1..=26 => char::from(b'A' + code as u8 - 1),
+            27..=36 => char::from(b'0' + (code - 27) as u8),
+            _ => '?',
+        }
+    }
+
+    fn receive(&mut self, val: i16) {
+        let mut framebuffer = self.framebuffer.borrow_mut();
+        let len = framebuffer.cells.len();
+        if len == 0 {
+            return;
+        }
+        if val == LCD_CLEAR {
+            framebuffer.clear();
+            self.cursor = 0;
+        } else if val >= LCD_MOVE {
+            self.cursor = (val - LCD_MOVE) as usize % len;
+        } else {
+            framebuffer.cells[self.cursor] = Self::glyph(val);
+            self.cursor = (self.cursor + 1) % len;
+        }
+    }
+}
+
+impl Device for Lcd {
+    fn step(&mut self) -> Result<(), ExecError> {
+        for val in drain(&self.xbus) {
+            self.receive(val);
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> DeviceState {
+        DeviceState::Exec
+    }
+
+    fn ready(&self) -> bool {
+        self.xbus.as_ref().is_some_and(|x| x.incoming() > 0)
+    }
+
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError> {
+        attach_x0(&mut self.xbus, pin, attachment)
+    }
+
+    fn update_attachments(&mut self) {}
+
+    fn next_time_unit(&mut self) {}
+
+    fn reset(&mut self) {
+        self.framebuffer.borrow_mut().clear();
+        self.cursor = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        board::Board,
+        device::XBusWire,
+        devices::{
+            expander::DX300,
+            input::{Script, XBusInput},
+            mc::ChipModel,
+        },
+        net::NetKind,
+        testing::{add_chip, add_part},
+    };
+
+    fn feed(device: &mut dyn Device, values: &[i16]) {
+        let wire = XBusWire::new();
+        let pin = XBusPin::connect(&wire);
+        device
+            .attach(X0, Attachment::XBus(XBusPin::connect(&wire)))
+            .unwrap();
+        for val in values {
+            pin.write(*val);
+        }
+        while device.ready() {
+            device.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_digit_display() {
+        let mut display = DT2415::new();
+        let framebuffer = display.framebuffer();
+        assert_eq!(framebuffer.borrow().to_string(), "   \n   \n   \n");
+
+        feed(&mut display, &[4, 8]);
+        assert_eq!(display.digit(), Some(8));
+        assert_eq!(framebuffer.borrow().to_string(), " _ \n|_|\n|_|\n");
+
+        feed(&mut display, &[7]);
+        assert_eq!(framebuffer.borrow().rows(), vec![" _ ", "  |", "  |"]);
+
+        feed(&mut display, &[10]);
+        assert_eq!(display.digit(), None);
+        feed(&mut display, &[-1]);
+        assert_eq!(display.segments(), [false; 7]);
+    }
+
+    #[test]
+    fn test_lcd_packets() {
+        let mut lcd = Lcd::new(4, 2);
+        let framebuffer = lcd.framebuffer();
+        // "HI" on the top row, "42" at the end of the bottom one
+        feed(&mut lcd, &[8, 9, LCD_MOVE + 6, 31, 29]);
+        assert_eq!(framebuffer.borrow().rows(), vec!["HI  ", "  42"]);
+        assert_eq!(framebuffer.borrow().cell(1, 0), Some('I'));
+        assert_eq!(framebuffer.borrow().cell(4, 0), None);
+        assert_eq!(lcd.cursor(), 0);
+
+        feed(&mut lcd, &[LCD_CLEAR, 50]);
+        assert_eq!(framebuffer.borrow().to_string(), "?   \n    \n");
+    }
+
+    #[test]
+    fn test_lcd_on_board() {
+        let mut board = Board::new();
+        let net = board.add_net(NetKind::XBus);
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "top: mov acc x0\nadd 1\ntlt acc 4\n+ jmp top\nslp 9",
+            &[(X0, net)],
+        );
+        let lcd = Lcd::new(4, 1);
+        let framebuffer = lcd.framebuffer();
+        add_part(&mut board, Box::new(lcd), &[(X0, net)]);
+
+        board.advance().unwrap();
+        assert_eq!(framebuffer.borrow().rows(), vec![" ABC"]);
+    }
+
+    #[test]
+    fn test_digit_display_shares_net() {
+        let mut board = Board::new();
+        let net = board.add_net(NetKind::XBus);
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov 7 x0\nslp 9",
+            &[(X0, net)],
+        );
+        add_part(&mut board, Box::new(DX300::new()), &[(X0, net)]);
+        let display = DT2415::new();
+        let framebuffer = display.framebuffer();
+        add_part(&mut board, Box::new(display), &[(X0, net)]);
+
+        // the display shows the chip's write, not what the DX300 has up
+        board.advance().unwrap();
+        assert_eq!(framebuffer.borrow().rows(), vec![" _ ", "  |", "  |"]);
+        board.advance().unwrap();
+        assert_eq!(board.state().ticks, 0);
+    }
+
+    #[test]
+    fn test_digit_display_shares_net_with_radio() {
+        let mut board = Board::new();
+        let net = board.add_net(NetKind::XBus);
+        let radio = XBusInput::radio(Script::new(vec![vec![1, 2]]));
+        add_part(&mut board, Box::new(radio), &[(X0, net)]);
+        let display = DT2415::new();
+        let framebuffer = display.framebuffer();
+        add_part(&mut board, Box::new(display), &[(X0, net)]);
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov 7 x0\nslp 9",
+            &[(X0, net)],
+        );
+
+        // the radio's packets are left for a chip to read
+        board.advance().unwrap();
+        assert_eq!(framebuffer.borrow().rows(), vec![" _ ", "  |", "  |"]);
+        assert_eq!(
+            board.net(net).unwrap().probe().read_value_from_attachment(),
+            Some(1)
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{
+    code::{Pin, Pin::*, Register},
+    device::{Attachment, Device, DeviceState, SimpleIOPin, XBusPin},
+    error::ExecError,
+    value,
+};
+
+/// The DX300 digital expander: three simple I/O pins, `p0` to `p2`, run over
+/// XBus through `x0`.
+///
+/// Writing a value to `x0` sets each pin from one of its digits, the ones
+/// digit for `p0` up to the hundreds digit for `p2`: a non-zero digit drives
+/// the pin to 100, a zero to 0. The sign is ignored. Reading `x0` packs the
+/// pins back up the same way, each one counting as 1 if its net is at 50 or
+/// more.
+pub struct DX300 {
+    outputs: [i16; 3],
+    pins: [Option<SimpleIOPin>; 3],
+    xbus: Option<XBusPin>,
+    // the packed value last put up on `x0`
+    offered: Option<i16>,
+}
+
+impl DX300 {
+    pub fn new() -> Self {
+        DX300 {
+            outputs: [0; 3],
+            pins: Default::default(),
+            xbus: None,
+            offered: None,
+        }
+    }
+
+    /// The pins as a three-digit number of ones and zeroes.
+    pub fn packed(&self) -> i16 {
+        self.pins
+            .iter()
+            .rev()
+            .map(|pin| pin.as_ref().map_or(0, SimpleIOPin::read_from))
+            .fold(0, |acc, level| {
+                acc * 10 + i16::from(level >= value::SIMPLE_IO_THRESHOLD)
+            })
+    }
+
+    fn unpack(&mut self, val: i16) {
+        let mut digits = val.unsigned_abs();
+        for output in &mut self.outputs {
+            *output = if digits.is_multiple_of(10) {
+                value::SIMPLE_IO_MIN
+            } else {
+                value::SIMPLE_IO_MAX
+            };
+            digits /= 10;
+        }
+    }
+
+    fn pin_index(pin: Pin) -> Option<usize> {
+        match pin {
+            P0 => Some(0),
+            P1 => Some(1),
+            P2 => Some(2),
+            _ => None,
+        }
+    }
+}
+
+impl Default for DX300 {
+    fn default() -> Self {
+        DX300::new()
+    }
+}
+
+impl Device for DX300 {
+    fn step(&mut self) -> Result<(), ExecError> {
+        let Some(xbus) = self.xbus.clone() else {
+            return Ok(());
+        };
+        while let Some(val) = xbus.receive() {
+            self.unpack(val);
+        }
+
+        let packed = self.packed();
+        xbus.offer(packed);
+        self.offered = Some(packed);
+        Ok(())
+    }
+
+    fn state(&self) -> DeviceState {
+        DeviceState::Exec
+    }
+
+    // ready to take a write, to put the pins up again once they were read,
+    // or to catch up with a pin someone else drove
+    fn ready(&self) -> bool {
+        let Some(xbus) = &self.xbus else {
+            return false;
+        };
+        xbus.incoming() > 0 || xbus.is_written() || self.offered != Some(self.packed())
+    }
+
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError> {
+        match (pin, attachment) {
+            (X0, Attachment::XBus(x)) => {
+                x.listen();
+                self.xbus = Some(x);
+            }
+            (P0 | P1 | P2, Attachment::SimpleIO(x)) => {
+                if let Some(i) = Self::pin_index(pin) {
+                    self.pins[i] = Some(x);
+                }
+            }
+            _ => {
+                return Err(ExecError::InvalidRegister {
+                    register: Register::Pin(pin),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn update_attachments(&mut self) {
+        for (pin, val) in self.pins.iter().zip(self.outputs) {
+            if let Some(x) = pin {
+                x.push_update_with(val);
+            }
+        }
+    }
+
+    fn next_time_unit(&mut self) {}
+
+    fn reset(&mut self) {
+        self.outputs = [0; 3];
+        self.offered = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        board::Board,
+        devices::mc::ChipModel,
+        net::NetKind,
+        testing::{add_chip, add_part, add_part_on_new_nets},
+    };
+
+    #[test]
+    fn test_write_sets_pins() {
+        let mut board = Board::new();
+        let [xbus, pins @ ..] =
+            add_part_on_new_nets(&mut board, Box::new(DX300::new()), [X0, P0, P1, P2]);
+        let probes = pins.map(|net| board.net(net).unwrap().probe());
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov 101 x0\nslp 1\nmov -10 x0\nslp 1",
+            &[(X0, xbus)],
+        );
+
+        board.advance().unwrap();
+        let levels = probes.each_ref().map(|p| p.read_value_from_attachment());
+        assert_eq!(levels, [Some(100), Some(0), Some(100)]);
+
+        board.advance().unwrap();
+        let levels = probes.each_ref().map(|p| p.read_value_from_attachment());
+        assert_eq!(levels, [Some(0), Some(100), Some(0)]);
+    }
+
+    #[test]
+    fn test_read_packs_pins() {
+        let mut board = Board::new();
+        let [xbus, _, p1, p2] =
+            add_part_on_new_nets(&mut board, Box::new(DX300::new()), [X0, P0, P1, P2]);
+        let out = board.add_net(NetKind::SimpleIO);
+        let probe = board.net(out).unwrap().probe();
+        // something else drives p1 high and p2 just under the threshold
+        let Attachment::SimpleIO(level) = board.net(p1).unwrap().probe() else {
+            unreachable!()
+        };
+        level.push_update_with(75);
+        let Attachment::SimpleIO(level) = board.net(p2).unwrap().probe() else {
+            unreachable!()
+        };
+        level.push_update_with(49);
+
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov x0 p0\nslp 1",
+            &[(X0, xbus), (P0, out)],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(10));
+    }
+
+    #[test]
+    fn test_expanders_share_net() {
+        let mut board = Board::new();
+        let [xbus, p0, ..] =
+            add_part_on_new_nets(&mut board, Box::new(DX300::new()), [X0, P0, P1, P2]);
+        let pins = [(); 3].map(|_| board.add_net(NetKind::SimpleIO));
+        add_part(
+            &mut board,
+            Box::new(DX300::new()),
+            &[(X0, xbus), (P0, pins[0]), (P1, pins[1]), (P2, pins[2])],
+        );
+        let probes = pins.map(|net| board.net(net).unwrap().probe());
+        // the first one has 1 up, which the second must not unpack
+        let Attachment::SimpleIO(level) = board.net(p0).unwrap().probe() else {
+            unreachable!()
+        };
+        level.push_update_with(100);
+
+        board.advance().unwrap();
+        let levels = probes.each_ref().map(|p| p.read_value_from_attachment());
+        assert_eq!(levels, [Some(0), Some(0), Some(0)]);
+
+        // both take a chip's write
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov 10 x0\nslp 9",
+            &[(X0, xbus)],
+        );
+        board.advance().unwrap();
+        let levels = probes.each_ref().map(|p| p.read_value_from_attachment());
+        assert_eq!(levels, [Some(0), Some(100), Some(0)]);
+        board.advance().unwrap();
+        assert_eq!(board.state().ticks, 0);
+    }
+
+    #[test]
+    fn test_unpack() {
+        let cases = [
+            (0, [0, 0, 0]),
+            (1, [100, 0, 0]),
+            (999, [100; 3]),
+            (-120, [0, 100, 100]),
+        ];
+        for (val, outputs) in cases {
+            let mut dx = DX300::new();
+            dx.unpack(val);
+            assert_eq!(dx.outputs, outputs, "{}", val);
+        }
+    }
+}
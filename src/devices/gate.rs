@@ -0,0 +1,228 @@
+use crate::{
+    code::{Pin, Pin::*, Register},
+    device::{Attachment, Device, DeviceState, SimpleIOPin},
+    error::ExecError,
+    value,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GateKind {
+    /// LC70G04
+    Not,
+    /// LC70G08
+    And,
+    /// LC70G32
+    Or,
+    /// LC70G86
+    Xor,
+}
+
+impl GateKind {
+    fn inputs(&self) -> usize {
+        match self {
+            GateKind::Not => 1,
+            _ => 2,
+        }
+    }
+
+    fn eval(&self, a: bool, b: bool) -> bool {
+        match self {
+            GateKind::Not => !a,
+            GateKind::And => a && b,
+            GateKind::Or => a || b,
+            GateKind::Xor => a != b,
+        }
+    }
+}
+
+/// A logic gate, reading `p0` (and `p1` unless it is a NOT) and driving `p2`
+/// to 0 or 100. Gates have no delay: the board settles them after every
+/// round, so a chip sees the output change in the round after it changed an
+/// input.
+pub struct LogicGate {
+    kind: GateKind,
+    inputs: [Option<SimpleIOPin>; 2],
+    output: Option<SimpleIOPin>,
+    level: Option<i16>,
+}
+
+impl LogicGate {
+    pub fn new(kind: GateKind) -> Self {
+        LogicGate {
+            kind,
+            inputs: Default::default(),
+            output: None,
+            level: None,
+        }
+    }
+
+    pub fn kind(&self) -> GateKind {
+        self.kind
+    }
+
+    fn input(&self, idx: usize) -> bool {
+        self.inputs[idx]
+            .as_ref()
+            .is_some_and(|pin| pin.read_from() >= value::SIMPLE_IO_THRESHOLD)
+    }
+}
+
+impl Device for LogicGate {
+    fn step(&mut self) -> Result<(), ExecError> {
+        Ok(())
+    }
+
+    fn state(&self) -> DeviceState {
+        DeviceState::Exec
+    }
+
+    fn ready(&self) -> bool {
+        false
+    }
+
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError> {
+        match (pin, attachment) {
+            (P0, Attachment::SimpleIO(x)) => self.inputs[0] = Some(x),
+            (P1, Attachment::SimpleIO(x)) if self.kind.inputs() > 1 => self.inputs[1] = Some(x),
+            (P2, Attachment::SimpleIO(x)) => self.output = Some(x),
+            _ => {
+                return Err(ExecError::InvalidRegister {
+                    register: Register::Pin(pin),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    // the output is driven in `settle`, as soon as the inputs change
+    fn update_attachments(&mut self) {}
+
+    fn next_time_unit(&mut self) {}
+
+    fn reset(&mut self) {
+        self.level = None;
+    }
+
+    fn settle(&mut self) -> bool {
+        let level = if self.kind.eval(self.input(0), self.input(1)) {
+            value::SIMPLE_IO_MAX
+        } else {
+            value::SIMPLE_IO_MIN
+        };
+        if self.level == Some(level) {
+            return false;
+        }
+        self.level = Some(level);
+        if let Some(output) = &self.output {
+            output.push_update_with(level);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        board::Board,
+        device::SimpleIOWire,
+        devices::mc::ChipModel,
+        net::{NetId, NetKind},
+        testing::{add_chip, add_part},
+    };
+
+    fn add_gate(board: &mut Board, kind: GateKind, pins: &[(Pin, NetId)]) {
+        add_part(board, Box::new(LogicGate::new(kind)), pins);
+    }
+
+    #[test]
+    fn test_truth_tables() {
+        // outputs for the inputs (0, 0), (100, 0), (0, 100) and (100, 100)
+        let cases = [
+            (GateKind::Not, [100, 0, 100, 0]),
+            (GateKind::And, [0, 0, 0, 100]),
+            (GateKind::Or, [0, 100, 100, 100]),
+            (GateKind::Xor, [0, 100, 100, 0]),
+        ];
+        for (kind, expected) in cases {
+            let wires = [
+                SimpleIOWire::new(),
+                SimpleIOWire::new(),
+                SimpleIOWire::new(),
+            ];
+            let [a, b, out] = wires.each_ref().map(SimpleIOPin::connect);
+            let mut gate = LogicGate::new(kind);
+            for (pin, wire) in [P0, P1, P2].into_iter().zip(&wires) {
+                if pin == P1 && kind == GateKind::Not {
+                    continue;
+                }
+                gate.attach(pin, Attachment::SimpleIO(SimpleIOPin::connect(wire)))
+                    .unwrap();
+            }
+
+            for ((x, y), level) in [(0, 0), (100, 0), (0, 100), (100, 100)]
+                .into_iter()
+                .zip(expected)
+            {
+                a.push_update_with(x);
+                b.push_update_with(y);
+                gate.settle();
+                assert_eq!(out.read_from(), level, "{:?} {} {}", kind, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_not_has_no_p1() {
+        let mut board = Board::new();
+        let device = board.add_device(Box::new(LogicGate::new(GateKind::Not)));
+        let net = board.add_net(NetKind::SimpleIO);
+        assert!(board.connect(net, device, P1).is_err());
+    }
+
+    #[test]
+    fn test_zero_delay() {
+        let mut board = Board::new();
+        let [a, b, inverted, and, out] = [(); 5].map(|_| board.add_net(NetKind::SimpleIO));
+        let probe = board.net(out).unwrap().probe();
+        // NOT of a feeds an AND with b
+        add_gate(&mut board, GateKind::Not, &[(P0, a), (P2, inverted)]);
+        add_gate(
+            &mut board,
+            GateKind::And,
+            &[(P0, inverted), (P1, b), (P2, and)],
+        );
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "mov 100 p1\nslp 1",
+            &[(P0, a), (P1, b)],
+        );
+        // the AND output is already through both gates one round later
+        add_chip(
+            &mut board,
+            ChipModel::MC4000,
+            "nop\nmov p0 p1\nslp 1",
+            &[(P0, and), (P1, out)],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(100));
+    }
+
+    #[test]
+    fn test_loop_detected() {
+        let mut board = Board::new();
+        let net = board.add_net(NetKind::SimpleIO);
+        // a NOT driving its own input never settles
+        add_gate(&mut board, GateKind::Not, &[(P0, net), (P2, net)]);
+        assert_eq!(board.advance(), Err(ExecError::CombinationalLoop));
+
+        // a loop that does settle is fine
+        let mut board = Board::new();
+        let [a, b] = [(); 2].map(|_| board.add_net(NetKind::SimpleIO));
+        add_gate(&mut board, GateKind::Not, &[(P0, a), (P2, b)]);
+        add_gate(&mut board, GateKind::Not, &[(P0, b), (P2, a)]);
+        assert_eq!(board.advance(), Ok(()));
+    }
+}
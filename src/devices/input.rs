@@ -0,0 +1,341 @@
+use std::collections::VecDeque;
+
+use crate::{
+    code::{Pin, Pin::*, Register},
+    device::{Attachment, Device, DeviceState, SimpleIOPin, XBusPin},
+    error::ExecError,
+    value,
+};
+
+/// What an XBus input reads as when it has nothing queued.
+pub const NO_DATA: i16 = -999;
+
+/// The stimulus an input sees, one entry per time unit. The time unit is the
+/// board's `GlobalState::seconds` while that unit runs.
+pub struct Script<T>(Box<dyn Fn(u32) -> T>);
+
+impl<T: Clone + Default + 'static> Script<T> {
+    /// Plays `values` in order, one per time unit, and the default value once
+    /// they run out.
+    pub fn new(values: Vec<T>) -> Self {
+        Script(Box::new(move |seconds| {
+            values.get(seconds as usize).cloned().unwrap_or_default()
+        }))
+    }
+
+    pub fn from_fn(f: impl Fn(u32) -> T + 'static) -> Self {
+        Script(Box::new(f))
+    }
+
+    pub fn at(&self, seconds: u32) -> T {
+        (self.0)(seconds)
+    }
+}
+
+/// An input driving `p0` to a level from its script. The level changes at the
+/// very start of each time unit, before any chip steps.
+pub struct SimpleInput {
+    script: Script<i16>,
+    output: Option<SimpleIOPin>,
+    seconds: u32,
+    level: Option<i16>,
+}
+
+impl SimpleInput {
+    /// A push button, at 100 while held and 0 otherwise.
+    pub fn button(script: Script<bool>) -> Self {
+        SimpleInput::new(Script::from_fn(move |seconds| {
+            if script.at(seconds) {
+                value::SIMPLE_IO_MAX
+            } else {
+                value::SIMPLE_IO_MIN
+            }
+        }))
+    }
+
+    /// An analog sensor. Readings outside 0 to 100 are clamped.
+    pub fn sensor(script: Script<i16>) -> Self {
+        SimpleInput::new(Script::from_fn(move |seconds| {
+            value::clamp_simple_io(script.at(seconds).into())
+        }))
+    }
+
+    fn new(script: Script<i16>) -> Self {
+        SimpleInput {
+            script,
+            output: None,
+            seconds: 0,
+            level: None,
+        }
+    }
+}
+
+impl Device for SimpleInput {
+    fn step(&mut self) -> Result<(), ExecError> {
+        Ok(())
+    }
+
+    fn state(&self) -> DeviceState {
+        DeviceState::Exec
+    }
+
+    fn ready(&self) -> bool {
+        false
+    }
+
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError> {
+        match (pin, attachment) {
+            (P0, Attachment::SimpleIO(x)) => self.output = Some(x),
+            _ => {
+                return Err(ExecError::InvalidRegister {
+                    register: Register::Pin(pin),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    // the output is driven in `settle`, which the board runs before the first
+    // round of every time unit
+    fn update_attachments(&mut self) {}
+
+    fn next_time_unit(&mut self) {
+        self.seconds += 1;
+    }
+
+    fn reset(&mut self) {
+        self.seconds = 0;
+        self.level = None;
+    }
+
+    fn settle(&mut self) -> bool {
+        let level = self.script.at(self.seconds);
+        if self.level == Some(level) {
+            return false;
+        }
+        self.level = Some(level);
+        if let Some(output) = &self.output {
+            output.push_update_with(level);
+        }
+        true
+    }
+}
+
+/// An input queueing up values from its script and handing them out over
+/// `x0`, oldest first. A unit's values arrive at its start. With nothing
+/// queued, `x0` reads as `NO_DATA`. It only sends.
+pub struct XBusInput {
+    script: Script<Vec<i16>>,
+    xbus: Option<XBusPin>,
+    seconds: u32,
+    queue: VecDeque<i16>,
+    // the value last put up on `x0`, and whether it came off the queue
+    offered: Option<i16>,
+    offered_queued: bool,
+}
+
+impl XBusInput {
+    /// A keypad, queueing up to one key press per time unit.
+    pub fn keypad(script: Script<Option<i16>>) -> Self {
+        XBusInput::new(Script::from_fn(move |seconds| {
+            script.at(seconds).into_iter().collect()
+        }))
+    }
+
+    /// The C2S-RF901 radio receiver, queueing every packet received.
+    pub fn radio(script: Script<Vec<i16>>) -> Self {
+        XBusInput::new(script)
+    }
+
+    fn new(script: Script<Vec<i16>>) -> Self {
+        let queue = script.at(0).into();
+        XBusInput {
+            script,
+            xbus: None,
+            seconds: 0,
+            queue,
+            offered: None,
+            offered_queued: false,
+        }
+    }
+
+    /// The values not read yet, oldest first.
+    pub fn queued(&self) -> &VecDeque<i16> {
+        &self.queue
+    }
+
+    fn head(&self) -> i16 {
+        self.queue.front().copied().unwrap_or(NO_DATA)
+    }
+}
+
+impl Device for XBusInput {
+    fn step(&mut self) -> Result<(), ExecError> {
+        let Some(xbus) = self.xbus.clone() else {
+            return Ok(());
+        };
+        if self.offered.is_some() && xbus.is_written() && self.offered_queued {
+            self.queue.pop_front();
+        }
+
+        let head = self.head();
+        xbus.offer(head);
+        self.offered = Some(head);
+        self.offered_queued = !self.queue.is_empty();
+        Ok(())
+    }
+
+    fn state(&self) -> DeviceState {
+        DeviceState::Exec
+    }
+
+    // ready to put the next value up once the last was read, or once a new
+    // unit brought something better than `NO_DATA`
+    fn ready(&self) -> bool {
+        let Some(xbus) = &self.xbus else {
+            return false;
+        };
+        (self.offered.is_some() && xbus.is_written()) || self.offered != Some(self.head())
+    }
+
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError> {
+        match (pin, attachment) {
+            (X0, Attachment::XBus(x)) => self.xbus = Some(x),
+            _ => {
+                return Err(ExecError::InvalidRegister {
+                    register: Register::Pin(pin),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn update_attachments(&mut self) {}
+
+    fn next_time_unit(&mut self) {
+        self.seconds += 1;
+        self.queue.extend(self.script.at(self.seconds));
+    }
+
+    fn reset(&mut self) {
+        self.seconds = 0;
+        self.queue = self.script.at(0).into();
+        self.offered = None;
+        self.offered_queued = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        board::Board,
+        devices::mc::ChipModel,
+        net::NetKind,
+        testing::{add_chip, add_part_on_new_nets},
+    };
+
+    #[test]
+    fn test_script() {
+        let script = Script::new(vec![4, 5]);
+        assert_eq!([0, 1, 2].map(|t| script.at(t)), [4, 5, 0]);
+        let script = Script::from_fn(|t| t % 2 == 0);
+        assert_eq!([0, 1, 2].map(|t| script.at(t)), [true, false, true]);
+    }
+
+    #[test]
+    fn test_button_and_sensor() {
+        let mut board = Board::new();
+        let [button] = add_part_on_new_nets(
+            &mut board,
+            Box::new(SimpleInput::button(Script::new(vec![true, false, true]))),
+            [P0],
+        );
+        let button = board.net(button).unwrap().probe();
+        let [sensor] = add_part_on_new_nets(
+            &mut board,
+            Box::new(SimpleInput::sensor(Script::from_fn(|t| t as i16 * 40))),
+            [P0],
+        );
+        let out = board.add_net(NetKind::SimpleIO);
+        let probe = board.net(out).unwrap().probe();
+        // the chip sees each unit's reading in its first round
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "mov p0 p1\nslp 1",
+            &[(P0, sensor), (P1, out)],
+        );
+
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            board.advance().unwrap();
+            seen.push((
+                button.read_value_from_attachment(),
+                probe.read_value_from_attachment(),
+            ));
+        }
+        assert_eq!(
+            seen,
+            [
+                (Some(100), Some(0)),
+                (Some(0), Some(40)),
+                (Some(100), Some(80)),
+                (Some(0), Some(100)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_radio_queue() {
+        let mut board = Board::new();
+        let radio = XBusInput::radio(Script::new(vec![vec![3, 4], vec![], vec![9]]));
+        let [radio] = add_part_on_new_nets(&mut board, Box::new(radio), [X0]);
+        let out = board.add_net(NetKind::SimpleIO);
+        let probe = board.net(out).unwrap().probe();
+        // one packet a unit, with 50 standing in for no packet
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "mov x0 acc\nteq acc -999\n+ mov 50 p0\n- mov acc p0\nslp 1",
+            &[(X0, radio), (P0, out)],
+        );
+
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            board.advance().unwrap();
+            seen.push(probe.read_value_from_attachment());
+        }
+        assert_eq!(seen, [Some(3), Some(4), Some(9), Some(50), Some(50)]);
+    }
+
+    #[test]
+    fn test_keypad_holds_presses() {
+        let mut board = Board::new();
+        let keypad = XBusInput::keypad(Script::new(vec![Some(1), None, Some(2)]));
+        let [keypad] = add_part_on_new_nets(&mut board, Box::new(keypad), [X0]);
+        let out = board.add_net(NetKind::SimpleIO);
+        let probe = board.net(out).unwrap().probe();
+        // nothing reads the keypad until both presses are in
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "slp 3\nmov x0 acc\nadd x0\nmov acc p0\nslp 9",
+            &[(X0, keypad), (P0, out)],
+        );
+
+        for _ in 0..4 {
+            board.advance().unwrap();
+        }
+        assert_eq!(probe.read_value_from_attachment(), Some(3));
+    }
+
+    #[test]
+    fn test_reset_replays_script() {
+        let mut radio = XBusInput::radio(Script::new(vec![vec![1], vec![2]]));
+        radio.next_time_unit();
+        assert_eq!(radio.queued(), &[1, 2]);
+        Device::reset(&mut radio);
+        assert_eq!(radio.queued(), &[1]);
+    }
+}
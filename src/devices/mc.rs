@@ -1,138 +1,358 @@
 use crate::{
-    code::{Loc, Opcode, Pin::*, Register},
-    device::{Code, CondState, DeviceState, McDevice, SimpleIOWire, XBusWire},
+    code::{parse_loc, Label, Loc, Opcode, Pin, Pin::*, Program, RegImm, Register},
+    device::{Attachment, Code, CondState, Device, DeviceState, McDevice, SimpleIOPin, XBusPin},
+    error::{ExecError, ParseError, Span},
 };
 
-pub fn execute_loc(loc: Loc, device: &mut dyn McDevice) {
-    let op = loc.op.unwrap();
-    match op {
+/// The microcontrollers a listing can be written for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChipModel {
+    MC4000,
+    MC4000X,
+    MC6000,
+}
+
+impl ChipModel {
+    pub fn max_lines(&self) -> usize {
+        match self {
+            ChipModel::MC4000 | ChipModel::MC4000X => 9,
+            ChipModel::MC6000 => 14,
+        }
+    }
+
+    pub fn has_register(&self, reg: Register) -> bool {
+        match (self, reg) {
+            (_, Register::Acc) => true,
+            (_, Register::Pin(P2)) => false,
+            (ChipModel::MC6000, _) => true,
+            (_, Register::Dat) => false,
+            (ChipModel::MC4000, Register::Pin(p)) => matches!(p, P0 | P1 | X0 | X1),
+            (ChipModel::MC4000X, Register::Pin(p)) => p.is_xbus(),
+        }
+    }
+
+    /// Loads `program` onto a fresh chip of this model.
+    pub fn build(&self, program: &Program) -> Result<Box<dyn Device>, ParseError> {
+        Ok(match self {
+            ChipModel::MC4000 => Box::new(MC4000::new(program)?),
+            ChipModel::MC4000X => Box::new(MC4000X::new(program)?),
+            ChipModel::MC6000 => Box::new(MC6000::new(program)?),
+        })
+    }
+
+    /// Checks that `source` would run on this chip, collecting the problems
+    /// of every line rather than stopping at the first one. Labels are only
+    /// checked once every line lexes.
+    pub fn validate(&self, source: &str) -> Result<Program, Vec<ParseError>> {
+        let mut errors = Vec::new();
+
+        for (line, text) in source.lines().enumerate() {
+            match parse_loc(line, text) {
+                Ok(loc) => errors.extend(self.check_registers(line, text, &loc)),
+                Err(err) => errors.push(err),
+            }
+        }
+        let lines = source.lines().count();
+        if lines > self.max_lines() {
+            errors.push(ParseError::TooManyLines {
+                lines,
+                max: self.max_lines(),
+            });
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Program::parse(source).map_err(|err| vec![err])
+    }
+
+    fn check_registers(&self, line: usize, text: &str, loc: &Loc) -> Vec<ParseError> {
+        let mut seen: Vec<Register> = Vec::new();
+        let mut errors = Vec::new();
+        for operand in loc.op.iter().flat_map(Opcode::operands) {
+            let RegImm::Reg(register) = operand else {
+                continue;
+            };
+            let nth = seen.iter().filter(|r| **r == register).count();
+            seen.push(register);
+            if !self.has_register(register) {
+                errors.push(ParseError::InvalidRegister {
+                    span: Span {
+                        line,
+                        column: register_column(text, register, nth),
+                    },
+                    register,
+                });
+            }
+        }
+        errors
+    }
+}
+
+/// Column of the `nth` operand of `text` naming `register`, in any case.
+/// Operands are always set apart by spaces or tabs, so they are whole words
+/// of the line once its comment is gone.
+fn register_column(text: &str, register: Register, nth: usize) -> usize {
+    let code = text.split('#').next().unwrap_or_default();
+    let mut column = 0;
+    let mut found = 0;
+    for word in code.split([' ', '\t']) {
+        if word.eq_ignore_ascii_case(register.name()) {
+            if found == nth {
+                return column;
+            }
+            found += 1;
+        }
+        column += word.len() + 1;
+    }
+    0
+}
+
+/// Runs one line on `device`. A line without an instruction does nothing.
+pub fn execute_loc(loc: &Loc, device: &mut dyn McDevice) -> Result<(), ExecError> {
+    let Some(op) = &loc.op else {
+        return Ok(());
+    };
+    match *op {
         Opcode::Nop => {}
         Opcode::MovReg(r1, r2) => {
-            let out = device.read_reg_or_imm(r1);
-            device.write_reg(r2, out);
-        }
-        Opcode::Jmp(label) => {
-            // TODO: jump to label
+            let out = device.read_reg_or_imm(r1)?;
+            device.write_clamped(r2, out.into())?;
         }
+        Opcode::Jmp(ref label) => device.jump(label)?,
         Opcode::Slp(r) => {
-            // TODO: wait til start of next second. or nth second
-            // there are actually an infinite number of timesteps between
-            // this second and the next second, but for practical reasons,
-            // we can put a hard cap and throw a part not sleeping error.
-            let out = device.read_reg_or_imm(r).try_into().unwrap();
-            device.sleep(out);
+            // like in the game, sleeping for zero or fewer time units is a no-op
+            let duration = device.read_reg_or_imm(r)?;
+            if duration > 0 {
+                device.sleep(duration as u32);
+            }
         }
+        Opcode::Slx(p) => match device.xbus_available(p) {
+            None => {
+                return Err(ExecError::InvalidRegister {
+                    register: Register::Pin(p),
+                })
+            }
+            Some(0) => device.set_state(DeviceState::SleepXBus(p)),
+            Some(_) => {}
+        },
         Opcode::Add(r) => {
-            let out = device.read_reg(Register::Acc) + device.read_reg_or_imm(r);
-            device.write_reg(Register::Acc, out)
+            let out =
+                i32::from(device.read_reg(Register::Acc)?) + i32::from(device.read_reg_or_imm(r)?);
+            device.write_clamped(Register::Acc, out)?;
         }
         Opcode::Sub(r) => {
-            let out = device.read_reg(Register::Acc) - device.read_reg_or_imm(r);
-            device.write_reg(Register::Acc, out)
+            let out =
+                i32::from(device.read_reg(Register::Acc)?) - i32::from(device.read_reg_or_imm(r)?);
+            device.write_clamped(Register::Acc, out)?;
         }
         Opcode::Mul(r) => {
-            let out = device.read_reg(Register::Acc) * device.read_reg_or_imm(r);
-            device.write_reg(Register::Acc, out)
+            let out =
+                i32::from(device.read_reg(Register::Acc)?) * i32::from(device.read_reg_or_imm(r)?);
+            device.write_clamped(Register::Acc, out)?;
         }
         Opcode::Not => {
-            let out = if device.read_reg(Register::Acc) == 0 {
+            let out = if device.read_reg(Register::Acc)? == 0 {
                 100
             } else {
                 0
             };
-            device.write_reg(Register::Acc, out)
+            device.write_clamped(Register::Acc, out)?;
         }
         Opcode::Dgt(r) => {
-            let pos = device.read_reg_or_imm(r) + 1;
-            let acc = device.read_reg(Register::Acc);
-            // need some value check
-            let sign = acc.signum();
-            let next = sign * (acc.abs() % 10i16.pow(pos as u32)) / 10i16.pow(pos as u32);
-            device.write_reg(Register::Acc, next)
+            let pos = device.read_reg_or_imm(r)?;
+            let acc = device.read_reg(Register::Acc)?;
+            device.write_reg(Register::Acc, digit(acc, pos))?;
         }
         Opcode::Dst(r1, r2) => {
-            let acc = device.read_reg(Register::Acc);
-            let pos = device.read_reg_or_imm(r1) + 1;
-            let dgt = device.read_reg_or_imm(r2); // TODO: check that it's between 0-9.
-            if !(0..9).contains(&dgt) {
-                panic!("invalid dst value");
+            let acc = device.read_reg(Register::Acc)?;
+            let pos = device.read_reg_or_imm(r1)?;
+            let dgt = device.read_reg_or_imm(r2)?;
+            if !(0..=9).contains(&dgt) {
+                return Err(ExecError::ValueOutOfRange { value: dgt });
             }
-            let sign = acc.signum();
-            let next = acc - sign * (acc.abs() % 10i16.pow(pos as u32))
-                + sign * (acc.abs() % 10i16.pow(pos as u32 - 1))
-                + sign * dgt * 10i16.pow(pos as u32);
-            device.write_reg(Register::Acc, next)
+            device.write_reg(Register::Acc, set_digit(acc, pos, dgt))?;
         }
-        Opcode::Teq(r1, r2) => match device.read_two_regimm(r1, r2) {
+        Opcode::Teq(r1, r2) => match device.read_two_regimm(r1, r2)? {
             (x, y) if x == y => device.set_condition(CondState::Plus),
             (_, _) => device.set_condition(CondState::Minus),
         },
-        Opcode::Tgt(r1, r2) => match device.read_two_regimm(r1, r2) {
+        Opcode::Tgt(r1, r2) => match device.read_two_regimm(r1, r2)? {
             (x, y) if x > y => device.set_condition(CondState::Plus),
             (_, _) => device.set_condition(CondState::Minus),
         },
 
-        Opcode::Tlt(r1, r2) => match device.read_two_regimm(r1, r2) {
+        Opcode::Tlt(r1, r2) => match device.read_two_regimm(r1, r2)? {
             (x, y) if x < y => device.set_condition(CondState::Plus),
             (_, _) => device.set_condition(CondState::Minus),
         },
-        Opcode::Tcp(r1, r2) => match device.read_two_regimm(r1, r2) {
+        Opcode::Tcp(r1, r2) => match device.read_two_regimm(r1, r2)? {
             (x, y) if x == y => device.set_condition(CondState::None),
             (x, y) if x > y => device.set_condition(CondState::Plus),
             (_, _) => device.set_condition(CondState::Minus),
         },
+        // gen P R1 R2 behaves like
+        //     mov 100 P
+        //     slp R1
+        //     mov 0 P
+        //     slp R2
+        // where the second half is held as `gen P 0 R2` to run on waking up
+        Opcode::Gen(p, r1, r2) => {
+            if p.is_xbus() {
+                return Err(ExecError::InvalidRegister {
+                    register: Register::Pin(p),
+                });
+            }
+            let (high, low) = device.read_two_regimm(r1, r2)?;
+            if high > 0 {
+                device.write_reg(Register::Pin(p), 100)?;
+                device.hold(Loc {
+                    op: Some(Opcode::Gen(p, RegImm::Imm(0), RegImm::Imm(low))),
+                    ..Loc::default()
+                });
+                device.sleep(high as u32);
+            } else {
+                // pin writes only land once the round is over, so a zero-length
+                // high phase never shows up on the pin
+                device.write_reg(Register::Pin(p), 0)?;
+                if low > 0 {
+                    device.sleep(low as u32);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The digit of `acc` at `pos`, where 0 is the ones digit, carrying the sign
+/// of `acc`. Positions outside 0..=2 read as 0.
+fn digit(acc: i16, pos: i16) -> i16 {
+    if !(0..=2).contains(&pos) {
+        return 0;
+    }
+    acc.signum() * (acc.abs() / 10i16.pow(pos as u32) % 10)
+}
+
+/// `acc` with the digit at `pos` replaced by `dgt`, keeping the sign of
+/// `acc`. Positions outside 0..=2 leave `acc` untouched.
+fn set_digit(acc: i16, pos: i16, dgt: i16) -> i16 {
+    if !(0..=2).contains(&pos) {
+        return acc;
+    }
+    let place = 10i16.pow(pos as u32);
+    let magnitude = acc.abs() - acc.abs() / place % 10 * place + dgt * place;
+    if acc < 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+// the line running only got fetched once every XBus read it makes had a value
+// waiting, see `McDevice::starved_pin`
+fn read_xbus(pin: &Option<XBusPin>) -> i16 {
+    pin.as_ref().and_then(XBusPin::read).unwrap_or_default()
+}
+
+fn xbus_available(pin: &Option<XBusPin>) -> usize {
+    pin.as_ref().map_or(0, XBusPin::available)
+}
+
+// an unconnected XBus pin never has its writes read
+fn xbus_written(pin: &Option<XBusPin>) -> bool {
+    pin.as_ref().is_some_and(XBusPin::is_written)
+}
+
+fn xbus_index(pin: Pin) -> Option<usize> {
+    match pin {
+        X0 => Some(0),
+        X1 => Some(1),
+        X2 => Some(2),
+        X3 => Some(3),
+        _ => None,
     }
 }
 
 pub struct MC4000 {
-    regs: [i16; 5],
+    regs: [i16; 3],
     state: DeviceState,
     code: Code<9>,
     attachments: (
-        Option<SimpleIOWire>,
-        Option<SimpleIOWire>,
-        Option<XBusWire>,
-        Option<XBusWire>,
+        Option<SimpleIOPin>,
+        Option<SimpleIOPin>,
+        Option<XBusPin>,
+        Option<XBusPin>,
     ),
 }
 
+impl MC4000 {
+    pub fn new(program: &Program) -> Result<Self, ParseError> {
+        Ok(MC4000 {
+            regs: [0; 3],
+            state: DeviceState::Exec,
+            code: Code::try_from(program)?,
+            attachments: (None, None, None, None),
+        })
+    }
+}
+
 impl McDevice for MC4000 {
-    fn read_reg(&self, reg: Register) -> i16 {
-        match reg {
+    fn read_reg(&self, reg: Register) -> Result<i16, ExecError> {
+        Ok(match reg {
             Register::Acc => self.regs[0],
-            Register::Dat => panic!("dat does not exist"),
+            Register::Dat => return Err(ExecError::InvalidRegister { register: reg }),
             Register::Pin(p) => match p {
-                P0 => self.attachments.0.map(|x| x.read_from()).unwrap_or(0),
-                P1 => self.attachments.1.map(|x| x.read_from()).unwrap_or(0),
-                X0 => todo!(),
-                X1 => todo!(),
-                X2 => panic!("x2 does not exist"),
-                X3 => panic!("x3 does not exist"),
+                P0 => self
+                    .attachments
+                    .0
+                    .as_ref()
+                    .map_or(0, SimpleIOPin::read_from),
+                P1 => self
+                    .attachments
+                    .1
+                    .as_ref()
+                    .map_or(0, SimpleIOPin::read_from),
+                X0 => read_xbus(&self.attachments.2),
+                X1 => read_xbus(&self.attachments.3),
+                P2 | X2 | X3 => return Err(ExecError::InvalidRegister { register: reg }),
             },
-        }
+        })
     }
 
-    fn set_condition(&mut self, state: crate::device::CondState) {
+    fn set_condition(&mut self, state: CondState) {
         self.code.state = state;
     }
 
-    fn sleep(&mut self, duration: u32) {
-        self.state = DeviceState::Sleep(duration);
+    fn set_state(&mut self, state: DeviceState) {
+        self.state = state;
+    }
+
+    fn jump(&mut self, label: &Label) -> Result<(), ExecError> {
+        self.code.jump(label)
     }
 
-    fn write_reg(&mut self, reg: Register, val: i16) {
+    fn write_reg(&mut self, reg: Register, val: i16) -> Result<(), ExecError> {
         match reg {
             Register::Acc => self.regs[0] = val,
-            Register::Dat => panic!("dat does not exist"),
+            Register::Dat => return Err(ExecError::InvalidRegister { register: reg }),
             Register::Pin(p) => match p {
                 P0 => self.regs[1] = val,
                 P1 => self.regs[2] = val,
-                X0 => self.regs[3] = val,
-                X1 => self.regs[4] = val,
-                X2 => panic!("x2 does not exist"),
-                X3 => panic!("x3 does not exist"),
+                X0 | X1 => {
+                    let attachment = if p == X0 {
+                        &self.attachments.2
+                    } else {
+                        &self.attachments.3
+                    };
+                    if let Some(x) = attachment {
+                        x.write(val);
+                    }
+                    self.state = DeviceState::Write(p);
+                }
+                P2 | X2 | X3 => return Err(ExecError::InvalidRegister { register: reg }),
             },
         };
+        Ok(())
     }
 
     fn get_state(&self) -> DeviceState {
@@ -143,71 +363,122 @@ impl McDevice for MC4000 {
         self.code.get_next_line()
     }
 
+    fn line(&self) -> usize {
+        self.code.line()
+    }
+
+    fn hold(&mut self, loc: Loc) {
+        self.code.hold(loc);
+    }
+
+    fn held(&self) -> Option<&Loc> {
+        self.code.held()
+    }
+
+    fn reset(&mut self) {
+        self.regs.fill(0);
+        self.state = DeviceState::Exec;
+        self.code.reset();
+    }
+
+    fn xbus_available(&self, pin: Pin) -> Option<usize> {
+        match pin {
+            X0 => Some(xbus_available(&self.attachments.2)),
+            X1 => Some(xbus_available(&self.attachments.3)),
+            _ => None,
+        }
+    }
+
+    fn xbus_written(&self, pin: Pin) -> bool {
+        match pin {
+            X0 => xbus_written(&self.attachments.2),
+            X1 => xbus_written(&self.attachments.3),
+            _ => false,
+        }
+    }
+
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError> {
+        match (pin, attachment) {
+            (P0, Attachment::SimpleIO(x)) => self.attachments.0 = Some(x),
+            (P1, Attachment::SimpleIO(x)) => self.attachments.1 = Some(x),
+            (X0, Attachment::XBus(x)) => self.attachments.2 = Some(x),
+            (X1, Attachment::XBus(x)) => self.attachments.3 = Some(x),
+            _ => {
+                return Err(ExecError::InvalidRegister {
+                    register: Register::Pin(pin),
+                })
+            }
+        }
+        Ok(())
+    }
+
     fn update_attachments(&mut self) {
-        if let Some(mut x) = self.attachments.0 {
+        if let Some(x) = &self.attachments.0 {
             x.push_update_with(self.regs[1]);
         }
-        if let Some(mut x) = self.attachments.1 {
+        if let Some(x) = &self.attachments.1 {
             x.push_update_with(self.regs[2]);
         }
-        if let Some(mut _x) = self.attachments.2 {
-            todo!()
-        }
-        if let Some(mut _x) = self.attachments.3 {
-            todo!()
-        }
     }
 }
 
 pub struct MC4000X {
-    regs: [i16; 5],
+    regs: [i16; 1],
     state: DeviceState,
     code: Code<9>,
-    attachments: (
-        Option<XBusWire>,
-        Option<XBusWire>,
-        Option<XBusWire>,
-        Option<XBusWire>,
-    ),
+    attachments: [Option<XBusPin>; 4],
+}
+
+impl MC4000X {
+    pub fn new(program: &Program) -> Result<Self, ParseError> {
+        Ok(MC4000X {
+            regs: [0; 1],
+            state: DeviceState::Exec,
+            code: Code::try_from(program)?,
+            attachments: Default::default(),
+        })
+    }
 }
 
 impl McDevice for MC4000X {
-    fn read_reg(&self, reg: Register) -> i16 {
-        match reg {
+    fn read_reg(&self, reg: Register) -> Result<i16, ExecError> {
+        Ok(match reg {
             Register::Acc => self.regs[0],
-            Register::Dat => panic!("dat does not exist"),
-            Register::Pin(p) => match p {
-                P0 => panic!("p0 does not exist"),
-                P1 => panic!("p1 does not exist"),
-                X0 => todo!(),
-                X1 => todo!(),
-                X2 => todo!(),
-                X3 => todo!(),
+            Register::Dat => return Err(ExecError::InvalidRegister { register: reg }),
+            Register::Pin(p) => match xbus_index(p) {
+                Some(i) => read_xbus(&self.attachments[i]),
+                None => return Err(ExecError::InvalidRegister { register: reg }),
             },
-        }
+        })
     }
 
-    fn set_condition(&mut self, state: crate::device::CondState) {
+    fn set_condition(&mut self, state: CondState) {
         self.code.state = state;
     }
 
-    fn sleep(&mut self, duration: u32) {
-        self.state = DeviceState::Sleep(duration);
+    fn set_state(&mut self, state: DeviceState) {
+        self.state = state;
+    }
+
+    fn jump(&mut self, label: &Label) -> Result<(), ExecError> {
+        self.code.jump(label)
     }
 
-    fn write_reg(&mut self, reg: Register, val: i16) {
+    fn write_reg(&mut self, reg: Register, val: i16) -> Result<(), ExecError> {
         match reg {
             Register::Acc => self.regs[0] = val,
-            Register::Dat => panic!("dat does not exist"),
-            Register::Pin(p) => match p {
-                P0 => panic!("p0 does not exist"),
-                P1 => panic!("p1 does not exist"),
-                X0 => self.regs[1] = val,
-                X1 => self.regs[2] = val,
-                X2 => self.regs[3] = val,
-                X3 => self.regs[4] = val,
-            },
+            Register::Dat => return Err(ExecError::InvalidRegister { register: reg }),
+            Register::Pin(p) => {
+                let Some(i) = xbus_index(p) else {
+                    return Err(ExecError::InvalidRegister { register: reg });
+                };
+                if let Some(x) = &self.attachments[i] {
+                    x.write(val);
+                }
+                self.state = DeviceState::Write(p);
+            }
         };
+        Ok(())
     }
 
     fn get_state(&self) -> DeviceState {
@@ -218,55 +489,116 @@ impl McDevice for MC4000X {
         self.code.get_next_line()
     }
 
-    fn update_attachments(&mut self) {
-        todo!()
+    fn line(&self) -> usize {
+        self.code.line()
+    }
+
+    fn hold(&mut self, loc: Loc) {
+        self.code.hold(loc);
+    }
+
+    fn held(&self) -> Option<&Loc> {
+        self.code.held()
+    }
+
+    fn reset(&mut self) {
+        self.regs.fill(0);
+        self.state = DeviceState::Exec;
+        self.code.reset();
+    }
+
+    fn xbus_available(&self, pin: Pin) -> Option<usize> {
+        xbus_index(pin).map(|i| xbus_available(&self.attachments[i]))
     }
+
+    fn xbus_written(&self, pin: Pin) -> bool {
+        xbus_index(pin).is_some_and(|i| xbus_written(&self.attachments[i]))
+    }
+
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError> {
+        match (xbus_index(pin), attachment) {
+            (Some(i), Attachment::XBus(x)) => self.attachments[i] = Some(x),
+            _ => {
+                return Err(ExecError::InvalidRegister {
+                    register: Register::Pin(pin),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    // all of its pins are XBus, so nothing is left to commit after a round
+    fn update_attachments(&mut self) {}
 }
 
 pub struct MC6000 {
-    regs: [i16; 8],
+    regs: [i16; 4],
     state: DeviceState,
-    code: Code<15>, // who knows tbh
-    attachments: ([Option<SimpleIOWire>; 2], [Option<XBusWire>; 4]),
+    code: Code<14>,
+    attachments: ([Option<SimpleIOPin>; 2], [Option<XBusPin>; 4]),
+}
+
+impl MC6000 {
+    pub fn new(program: &Program) -> Result<Self, ParseError> {
+        Ok(MC6000 {
+            regs: [0; 4],
+            state: DeviceState::Exec,
+            code: Code::try_from(program)?,
+            attachments: Default::default(),
+        })
+    }
 }
 
 impl McDevice for MC6000 {
-    fn read_reg(&self, reg: Register) -> i16 {
-        match reg {
+    fn read_reg(&self, reg: Register) -> Result<i16, ExecError> {
+        Ok(match reg {
             Register::Acc => self.regs[0],
             Register::Dat => self.regs[1],
             Register::Pin(p) => match p {
-                P0 => self.attachments.0[0].map(|x| x.read_from()).unwrap_or(0),
-                P1 => self.attachments.0[1].map(|x| x.read_from()).unwrap_or(0),
-                X0 => todo!(),
-                X1 => todo!(),
-                X2 => todo!(),
-                X3 => todo!(),
+                P0 => self.attachments.0[0]
+                    .as_ref()
+                    .map_or(0, SimpleIOPin::read_from),
+                P1 => self.attachments.0[1]
+                    .as_ref()
+                    .map_or(0, SimpleIOPin::read_from),
+                X0 => read_xbus(&self.attachments.1[0]),
+                X1 => read_xbus(&self.attachments.1[1]),
+                X2 => read_xbus(&self.attachments.1[2]),
+                X3 => read_xbus(&self.attachments.1[3]),
+                P2 => return Err(ExecError::InvalidRegister { register: reg }),
             },
-        }
+        })
     }
 
-    fn set_condition(&mut self, state: crate::device::CondState) {
+    fn set_condition(&mut self, state: CondState) {
         self.code.state = state;
     }
 
-    fn sleep(&mut self, duration: u32) {
-        self.state = DeviceState::Sleep(duration);
+    fn set_state(&mut self, state: DeviceState) {
+        self.state = state;
+    }
+
+    fn jump(&mut self, label: &Label) -> Result<(), ExecError> {
+        self.code.jump(label)
     }
 
-    fn write_reg(&mut self, reg: Register, val: i16) {
+    fn write_reg(&mut self, reg: Register, val: i16) -> Result<(), ExecError> {
         match reg {
             Register::Acc => self.regs[0] = val,
             Register::Dat => self.regs[1] = val,
             Register::Pin(p) => match p {
                 P0 => self.regs[2] = val,
                 P1 => self.regs[3] = val,
-                X0 => self.regs[4] = val,
-                X1 => self.regs[5] = val,
-                X2 => self.regs[6] = val,
-                X3 => self.regs[7] = val,
+                P2 => return Err(ExecError::InvalidRegister { register: reg }),
+                X0 | X1 | X2 | X3 => {
+                    if let Some(x) = xbus_index(p).and_then(|i| self.attachments.1[i].as_ref()) {
+                        x.write(val);
+                    }
+                    self.state = DeviceState::Write(p);
+                }
             },
-        }
+        };
+        Ok(())
     }
 
     fn get_state(&self) -> DeviceState {
@@ -277,7 +609,337 @@ impl McDevice for MC6000 {
         self.code.get_next_line()
     }
 
+    fn line(&self) -> usize {
+        self.code.line()
+    }
+
+    fn hold(&mut self, loc: Loc) {
+        self.code.hold(loc);
+    }
+
+    fn held(&self) -> Option<&Loc> {
+        self.code.held()
+    }
+
+    fn reset(&mut self) {
+        self.regs.fill(0);
+        self.state = DeviceState::Exec;
+        self.code.reset();
+    }
+
+    fn xbus_available(&self, pin: Pin) -> Option<usize> {
+        xbus_index(pin).map(|i| xbus_available(&self.attachments.1[i]))
+    }
+
+    fn xbus_written(&self, pin: Pin) -> bool {
+        xbus_index(pin).is_some_and(|i| xbus_written(&self.attachments.1[i]))
+    }
+
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError> {
+        match (pin, attachment) {
+            (P0, Attachment::SimpleIO(x)) => self.attachments.0[0] = Some(x),
+            (P1, Attachment::SimpleIO(x)) => self.attachments.0[1] = Some(x),
+            (X0 | X1 | X2 | X3, Attachment::XBus(x)) => {
+                if let Some(i) = xbus_index(pin) {
+                    self.attachments.1[i] = Some(x);
+                }
+            }
+            _ => {
+                return Err(ExecError::InvalidRegister {
+                    register: Register::Pin(pin),
+                })
+            }
+        }
+        Ok(())
+    }
+
     fn update_attachments(&mut self) {
-        todo!()
+        for (attachment, val) in self.attachments.0.iter().zip(&self.regs[2..]) {
+            if let Some(x) = attachment {
+                x.push_update_with(*val);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code::MAX_LINE_LEN;
+
+    #[test]
+    fn test_jmp() {
+        let program =
+            Program::parse("mov 1 acc\njmp skip\nmov 5 acc\nskip: add 1\njmp skip").unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+        for _ in 0..7 {
+            device.step().unwrap();
+        }
+        assert_eq!(device.read_reg(Register::Acc).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_arithmetic_saturates() {
+        let program =
+            Program::parse("mov 999 acc\nadd 1\nmul 999\nmov -999 acc\nsub 5\nmul 999").unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+        let expected = [999, 999, 999, -999, -999, -999];
+        for acc in expected {
+            device.step().unwrap();
+            assert_eq!(device.read_reg(Register::Acc).unwrap(), acc);
+        }
+    }
+
+    #[test]
+    fn test_slp() {
+        let program = Program::parse("slp 2\nslp 0\nslp -5\nadd 1").unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+        device.step().unwrap();
+        assert_eq!(device.state(), DeviceState::Sleep(2));
+
+        device.next_time_unit();
+        assert_eq!(device.state(), DeviceState::Sleep(1));
+        device.next_time_unit();
+        assert_eq!(device.state(), DeviceState::Exec);
+
+        for _ in 0..3 {
+            device.step().unwrap();
+        }
+        assert_eq!(device.state(), DeviceState::Exec);
+        assert_eq!(device.read_reg(Register::Acc).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_reset() {
+        let program = Program::parse("@ mov 5 acc\nadd 1\nslp 3").unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+        for _ in 0..3 {
+            device.step().unwrap();
+        }
+        assert_eq!(device.read_reg(Register::Acc).unwrap(), 6);
+        assert_eq!(device.state(), DeviceState::Sleep(3));
+
+        Device::reset(&mut device);
+        assert_eq!(device.state(), DeviceState::Exec);
+        assert_eq!(device.read_reg(Register::Acc).unwrap(), 0);
+        device.step().unwrap();
+        assert_eq!(device.read_reg(Register::Acc).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_digit() {
+        let cases = [
+            // (acc, pos, expected)
+            (596, 0, 6),
+            (596, 1, 9),
+            (596, 2, 5),
+            (7, 1, 0),
+            (-596, 0, -6),
+            (-596, 2, -5),
+            (0, 0, 0),
+            (596, 3, 0),
+            (596, -1, 0),
+            (-596, 999, 0),
+        ];
+        for (acc, pos, expected) in cases {
+            assert_eq!(digit(acc, pos), expected, "dgt {} on {}", pos, acc);
+        }
+    }
+
+    #[test]
+    fn test_set_digit() {
+        let cases = [
+            // (acc, pos, dgt, expected)
+            (596, 0, 1, 591),
+            (596, 1, 0, 506),
+            (596, 2, 9, 996),
+            (0, 0, 9, 9),
+            (0, 2, 3, 300),
+            (-596, 0, 1, -591),
+            (-596, 2, 0, -96),
+            (-5, 1, 4, -45),
+            (596, 3, 1, 596),
+            (-596, -1, 1, -596),
+        ];
+        for (acc, pos, dgt, expected) in cases {
+            assert_eq!(
+                set_digit(acc, pos, dgt),
+                expected,
+                "dst {} {} on {}",
+                pos,
+                dgt,
+                acc
+            );
+        }
+    }
+
+    #[test]
+    fn test_dgt_dst() {
+        let program = Program::parse("mov -596 acc\ndgt 1\ndst 2 9\ndst 0 7").unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+        for acc in [-596, -9, -909, -907] {
+            device.step().unwrap();
+            assert_eq!(device.read_reg(Register::Acc).unwrap(), acc);
+        }
+    }
+
+    fn at_line(line: usize, error: ExecError) -> Result<(), ExecError> {
+        Err(ExecError::Line {
+            line,
+            error: Box::new(error),
+        })
+    }
+
+    #[test]
+    fn test_dst_digit_out_of_range() {
+        let program = Program::parse("dst 0 10").unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+        assert_eq!(
+            device.step(),
+            at_line(0, ExecError::ValueOutOfRange { value: 10 })
+        );
+    }
+
+    #[test]
+    fn test_invalid_register() {
+        let program = Program::parse("nop\n\nmov 1 dat").unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+        device.step().unwrap();
+        assert_eq!(
+            device.step(),
+            at_line(
+                2,
+                ExecError::InvalidRegister {
+                    register: Register::Dat
+                }
+            )
+        );
+
+        let program = Program::parse("mov p0 acc").unwrap();
+        let mut device = MC4000X::new(&program).unwrap();
+        assert_eq!(
+            device.step(),
+            at_line(
+                0,
+                ExecError::InvalidRegister {
+                    register: Register::Pin(P0)
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_execute_empty_line() {
+        let program = Program::parse("nop").unwrap();
+        let mut device = MC4000::new(&program).unwrap();
+        assert_eq!(execute_loc(&Loc::default(), &mut device), Ok(()));
+    }
+
+    #[test]
+    fn test_line_limits() {
+        let nine = Program::parse(&"nop\n".repeat(9)).unwrap();
+        let ten = Program::parse(&"nop\n".repeat(10)).unwrap();
+        assert!(MC4000::new(&nine).is_ok());
+        assert!(MC4000X::new(&nine).is_ok());
+        assert!(MC4000::new(&ten).is_err());
+        assert!(MC4000X::new(&ten).is_err());
+
+        let fourteen = Program::parse(&"nop\n".repeat(14)).unwrap();
+        let fifteen = Program::parse(&"nop\n".repeat(15)).unwrap();
+        assert!(MC6000::new(&fourteen).is_ok());
+        assert_eq!(
+            MC6000::new(&fifteen).err(),
+            Some(ParseError::TooManyLines { lines: 15, max: 14 })
+        );
+    }
+
+    #[test]
+    fn test_mc6000_dat() {
+        let program = Program::parse("mov 4 dat\nadd dat\nmul dat\nmov acc dat").unwrap();
+        let mut device = MC6000::new(&program).unwrap();
+        for _ in 0..4 {
+            device.step().unwrap();
+        }
+        assert_eq!(device.read_reg(Register::Acc).unwrap(), 16);
+        assert_eq!(device.read_reg(Register::Dat).unwrap(), 16);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ChipModel::MC6000.validate("mov x3 dat\nmov dat p1").is_ok());
+        assert!(ChipModel::MC6000.validate("slx p0").is_err());
+        assert!(ChipModel::MC6000.validate("gen x0 1 1").is_err());
+        assert!(ChipModel::MC6000.validate("mov 1 p2").is_err());
+        assert_eq!(
+            ChipModel::MC4000.validate("mov 1 acc\nmov acc dat\nmov x2 p0"),
+            Err(vec![
+                ParseError::InvalidRegister {
+                    span: Span { line: 1, column: 8 },
+                    register: Register::Dat,
+                },
+                ParseError::InvalidRegister {
+                    span: Span { line: 2, column: 4 },
+                    register: Register::Pin(X2),
+                },
+            ])
+        );
+        assert_eq!(
+            ChipModel::MC4000X.validate("gen p0 1 1"),
+            Err(vec![ParseError::InvalidRegister {
+                span: Span { line: 0, column: 4 },
+                register: Register::Pin(P0),
+            }])
+        );
+        // columns count from wherever the register is written, in any case
+        assert_eq!(
+            ChipModel::MC4000.validate("dat: MOV 1 DAT\nmov\tdat dat"),
+            Err(vec![
+                ParseError::InvalidRegister {
+                    span: Span {
+                        line: 0,
+                        column: 11
+                    },
+                    register: Register::Dat,
+                },
+                ParseError::InvalidRegister {
+                    span: Span { line: 1, column: 4 },
+                    register: Register::Dat,
+                },
+                ParseError::InvalidRegister {
+                    span: Span { line: 1, column: 8 },
+                    register: Register::Dat,
+                },
+            ])
+        );
+        // one line failing to parse does not hide the problems of the others
+        assert_eq!(
+            ChipModel::MC4000.validate("slx p0\nmov 1 dat\ngen x0 1 1"),
+            Err(vec![
+                ParseError::Syntax {
+                    span: Span { line: 0, column: 0 },
+                    rest: String::from("slx p0"),
+                },
+                ParseError::InvalidRegister {
+                    span: Span { line: 1, column: 6 },
+                    register: Register::Dat,
+                },
+                ParseError::Syntax {
+                    span: Span { line: 2, column: 0 },
+                    rest: String::from("gen x0 1 1"),
+                },
+            ])
+        );
+        let source = format!("mov 100 acc # far too long\n{}", "nop\n".repeat(9));
+        assert_eq!(
+            ChipModel::MC4000.validate(&source),
+            Err(vec![
+                ParseError::LineTooLong {
+                    line: 0,
+                    len: 26,
+                    max: MAX_LINE_LEN,
+                },
+                ParseError::TooManyLines { lines: 10, max: 9 },
+            ])
+        );
     }
 }
@@ -0,0 +1,309 @@
+use crate::{
+    code::{Pin, Pin::*, Register},
+    device::{Attachment, Device, DeviceState, XBusPin},
+    error::ExecError,
+    value,
+};
+
+/// Cells in both the 200P-33 RAM and the 100P-14 ROM.
+pub const CELLS: usize = 14;
+
+/// One address/data pair of XBus pins and the cell it points at.
+#[derive(Default)]
+struct Port {
+    address: Option<XBusPin>,
+    data: Option<XBusPin>,
+    pointer: usize,
+    // set once a cell has been put up on the data pin, after which finding
+    // nothing of ours left on the wire means it was read
+    offered: bool,
+}
+
+/// The 200P-33 RAM and 100P-14 ROM. `a0`/`d0` sit on `x0`/`x1` and `a1`/`d1`
+/// on `x2`/`x3`.
+///
+/// Both pins of a port always have a value up for reading, the pointer on
+/// the address pin and the cell it points at on the data pin. Reading or
+/// writing the data pin moves the pointer on by one cell.
+///
+/// In the game no two accesses ever happen at once: chips run one
+/// instruction at a time, so of two writes to a cell the later one sticks,
+/// and a read sees every write that ran before it. Here all of a round's
+/// accesses reach the memory together, so it settles them in a fixed order
+/// that gives one of the outcomes the game can: reads first, then port 0's
+/// writes, then port 1's. Of two writes to the same cell in one round the one
+/// through port 1 sticks, and a read made alongside a write to its cell gets
+/// the value from before the write. From the next round on, either port
+/// reads what the other wrote.
+pub struct Memory {
+    cells: [i16; CELLS],
+    initial: [i16; CELLS],
+    writable: bool,
+    ports: [Port; 2],
+}
+
+impl Memory {
+    /// A 200P-33, with every cell 0.
+    pub fn ram() -> Self {
+        Memory {
+            cells: [0; CELLS],
+            initial: [0; CELLS],
+            writable: true,
+            ports: Default::default(),
+        }
+    }
+
+    /// A 100P-14 holding `values`, from cell 0 on. Cells past the end of
+    /// `values` are 0, and values past the last cell are dropped.
+    pub fn rom(values: &[i16]) -> Self {
+        let mut cells = [0; CELLS];
+        for (cell, val) in cells.iter_mut().zip(values) {
+            *cell = value::clamp((*val).into());
+        }
+        Memory {
+            cells,
+            initial: cells,
+            writable: false,
+            ports: Default::default(),
+        }
+    }
+
+    pub fn cells(&self) -> &[i16; CELLS] {
+        &self.cells
+    }
+
+    fn port_mut(&mut self, pin: Pin) -> Option<&mut Option<XBusPin>> {
+        match pin {
+            X0 => Some(&mut self.ports[0].address),
+            X1 => Some(&mut self.ports[0].data),
+            X2 => Some(&mut self.ports[1].address),
+            X3 => Some(&mut self.ports[1].data),
+            _ => None,
+        }
+    }
+}
+
+impl Device for Memory {
+    fn step(&mut self) -> Result<(), ExecError> {
+        for idx in 0..self.ports.len() {
+            let port = &mut self.ports[idx];
+            if let Some(data) = &port.data {
+                if port.offered && data.is_written() {
+                    port.pointer = (port.pointer + 1) % CELLS;
+                }
+            }
+            if let Some(address) = &port.address {
+                while let Some(val) = address.receive() {
+                    port.pointer = (val as isize).rem_euclid(CELLS as isize) as usize;
+                }
+            }
+            if let Some(data) = &port.data {
+                while let Some(val) = data.receive() {
+                    if self.writable {
+                        self.cells[port.pointer] = val;
+                    }
+                    port.pointer = (port.pointer + 1) % CELLS;
+                }
+            }
+        }
+
+        // a write through one port can change what the other one has up
+        for port in &mut self.ports {
+            if let Some(address) = &port.address {
+                address.offer(port.pointer as i16);
+            }
+            if let Some(data) = &port.data {
+                data.offer(self.cells[port.pointer]);
+                port.offered = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> DeviceState {
+        DeviceState::Exec
+    }
+
+    fn ready(&self) -> bool {
+        self.ports.iter().any(|port| {
+            let incoming = [&port.address, &port.data]
+                .into_iter()
+                .flatten()
+                .any(|pin| pin.incoming() > 0);
+            let taken = [&port.address, &port.data]
+                .into_iter()
+                .flatten()
+                .any(XBusPin::is_written);
+            incoming || taken
+        })
+    }
+
+    fn attach(&mut self, pin: Pin, attachment: Attachment) -> Result<(), ExecError> {
+        match (self.port_mut(pin), attachment) {
+            (Some(slot), Attachment::XBus(x)) => {
+                x.listen();
+                *slot = Some(x);
+            }
+            _ => {
+                return Err(ExecError::InvalidRegister {
+                    register: Register::Pin(pin),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn update_attachments(&mut self) {}
+
+    fn next_time_unit(&mut self) {}
+
+    fn reset(&mut self) {
+        self.cells = self.initial;
+        for port in &mut self.ports {
+            port.pointer = 0;
+            port.offered = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        board::Board,
+        devices::mc::ChipModel,
+        net::NetKind,
+        testing::{add_chip, add_part_on_new_nets},
+    };
+
+    #[test]
+    fn test_rom_lookup() {
+        let mut board = Board::new();
+        let [a0, d0, ..] = add_part_on_new_nets(
+            &mut board,
+            Box::new(Memory::rom(&[5, 10, 15, 20])),
+            [X0, X1, X2, X3],
+        );
+        let out = board.add_net(NetKind::SimpleIO);
+        let probe = board.net(out).unwrap().probe();
+        // reads cell 2 and then the one after it
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "mov 2 x0\nmov x1 acc\nadd x1\nmov acc p0\nslp 1",
+            &[(X0, a0), (X1, d0), (P0, out)],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(35));
+    }
+
+    #[test]
+    fn test_ram_ports() {
+        let mut board = Board::new();
+        let [a0, d0, a1, d1] =
+            add_part_on_new_nets(&mut board, Box::new(Memory::ram()), [X0, X1, X2, X3]);
+        let out = board.add_net(NetKind::SimpleIO);
+        let probe = board.net(out).unwrap().probe();
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "mov 3 x0\nmov 7 x1\nmov 8 x1\nslp 1",
+            &[(X0, a0), (X1, d0)],
+        );
+        // port 1 sees port 0's writes as soon as they land
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "slp 1\nmov 3 x2\nmov x3 acc\nmul x3\nmov acc p0\nslp 9",
+            &[(X2, a1), (X3, d1), (P0, out)],
+        );
+
+        board.advance().unwrap();
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(56));
+    }
+
+    #[test]
+    fn test_ram_same_round_contention() {
+        let mut board = Board::new();
+        let [a0, d0, a1, d1] =
+            add_part_on_new_nets(&mut board, Box::new(Memory::ram()), [X0, X1, X2, X3]);
+        let [first, second] = [(); 2].map(|_| board.add_net(NetKind::SimpleIO));
+        let probes = [first, second].map(|net| board.net(net).unwrap().probe());
+        // both chips point at cell 3 and then access it in the same rounds:
+        // first port 0 writes 7 while port 1 writes 8, then port 0 writes 5
+        // while port 1 reads
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "mov 3 x0\nmov 7 x1\nmov 3 x0\nmov 5 x1\nslp 9",
+            &[(X0, a0), (X1, d0)],
+        );
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "mov 3 x2\nmov 8 x3\nmov 3 x2\nmov x3 p0\nslp 1\nmov 3 x2\nmov x3 p1\nslp 9",
+            &[(X2, a1), (X3, d1), (P0, first), (P1, second)],
+        );
+
+        board.advance().unwrap();
+        // port 1's write stuck, and the read did not see the write made
+        // alongside it
+        assert_eq!(probes[0].read_value_from_attachment(), Some(8));
+        board.advance().unwrap();
+        assert_eq!(probes[1].read_value_from_attachment(), Some(5));
+    }
+
+    #[test]
+    fn test_ram_address_wraps() {
+        let mut board = Board::new();
+        let [a0, d0, ..] =
+            add_part_on_new_nets(&mut board, Box::new(Memory::ram()), [X0, X1, X2, X3]);
+        let out = board.add_net(NetKind::SimpleIO);
+        let probe = board.net(out).unwrap().probe();
+        // 13 is the last cell, so the write after it lands in cell 0
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "mov 13 x0\nmov 1 x1\nmov 2 x1\nmov x0 p0\nslp 9",
+            &[(X0, a0), (X1, d0), (P0, out)],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(1));
+    }
+
+    #[test]
+    fn test_rom_ignores_writes() {
+        assert_eq!(&Memory::rom(&[1, 2, 3]).cells()[..4], &[1, 2, 3, 0]);
+        assert_eq!(Memory::ram().cells(), &[0; CELLS]);
+
+        let mut board = Board::new();
+        let [a0, d0, ..] = add_part_on_new_nets(
+            &mut board,
+            Box::new(Memory::rom(&[1, 2, 3])),
+            [X0, X1, X2, X3],
+        );
+        let out = board.add_net(NetKind::SimpleIO);
+        let probe = board.net(out).unwrap().probe();
+        // the write still moves the pointer on, but cell 0 keeps its value
+        add_chip(
+            &mut board,
+            ChipModel::MC6000,
+            "mov 99 x1\nmov x1 acc\nmov 0 x0\nadd x1\nmov acc p0\nslp 9",
+            &[(X0, a0), (X1, d0), (P0, out)],
+        );
+
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(3));
+    }
+
+    #[test]
+    fn test_attach_rejects_simple_io() {
+        let mut board = Board::new();
+        let device = board.add_device(Box::new(Memory::ram()));
+        let net = board.add_net(NetKind::SimpleIO);
+        assert!(board.connect(net, device, P0).is_err());
+    }
+}
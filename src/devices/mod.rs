@@ -1 +1,15 @@
+//! The parts a board is built from.
+//!
+//! Apart from the microcontrollers, no part ever blocks a chip talking to it
+//! over XBus. Each offers a value on its XBus pins for reading, putting a
+//! fresh one up as soon as the last was read or went stale, and listens for
+//! whatever chips write to it, taking it straight away. Parts never take
+//! what another part offers, so any number of them can share a net. Parts
+//! that only send leave writes to them blocked.
+
+pub mod display;
+pub mod expander;
+pub mod gate;
+pub mod input;
 pub mod mc;
+pub mod memory;
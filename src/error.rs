@@ -0,0 +1,302 @@
+use std::fmt;
+
+use crate::{
+    code::{Label, Pin, Register},
+    net::{DeviceId, NetId, NetKind},
+};
+
+/// Position of an error in a listing. Both fields are zero-based.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.column + 1)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The mnemonic at `span` is not an instruction.
+    UnknownOpcode {
+        span: Span,
+        mnemonic: String,
+    },
+    /// The line could not be lexed from `span` onwards; `rest` is the
+    /// unconsumed tail of the line.
+    Syntax {
+        span: Span,
+        rest: String,
+    },
+    DuplicateLabel {
+        span: Span,
+        label: Label,
+    },
+    UndefinedLabel {
+        span: Span,
+        label: Label,
+    },
+    LineTooLong {
+        line: usize,
+        len: usize,
+        max: usize,
+    },
+    ValueOutOfRange {
+        span: Span,
+        value: i64,
+    },
+    TooManyLines {
+        lines: usize,
+        max: usize,
+    },
+    /// The register at `span` does not exist on the chip the listing is for.
+    InvalidRegister {
+        span: Span,
+        register: Register,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownOpcode { span, mnemonic } => {
+                write!(f, "{}: unknown instruction `{}`", span, mnemonic)
+            }
+            ParseError::Syntax { span, rest } => {
+                write!(f, "{}: could not parse `{}`", span, rest)
+            }
+            ParseError::DuplicateLabel { span, label } => {
+                write!(f, "{}: duplicate label `{}`", span, label)
+            }
+            ParseError::UndefinedLabel { span, label } => {
+                write!(f, "{}: undefined label `{}`", span, label)
+            }
+            ParseError::LineTooLong { line, len, max } => write!(
+                f,
+                "line {}: {} characters long, at most {} fit",
+                line + 1,
+                len,
+                max
+            ),
+            ParseError::ValueOutOfRange { span, value } => {
+                write!(f, "{}: {} is outside -999..=999", span, value)
+            }
+            ParseError::TooManyLines { lines, max } => {
+                write!(f, "program has {} lines, chip only fits {}", lines, max)
+            }
+            ParseError::InvalidRegister { span, register } => {
+                write!(f, "{}: chip has no register `{}`", span, register)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExecError {
+    /// The chip has no such register or pin.
+    InvalidRegister {
+        register: Register,
+    },
+    UndefinedLabel {
+        label: Label,
+    },
+    ValueOutOfRange {
+        value: i16,
+    },
+    /// A time unit ran for `ticks` steps without every device going to sleep
+    /// or blocking.
+    NotSleeping {
+        ticks: u32,
+    },
+    /// Logic gates feed back into each other and never settle.
+    CombinationalLoop,
+    /// Running the chip's `line`th line failed with `error`.
+    Line {
+        line: usize,
+        error: Box<ExecError>,
+    },
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::InvalidRegister { register } => {
+                write!(f, "chip has no register `{}`", register)
+            }
+            ExecError::UndefinedLabel { label } => {
+                write!(f, "undefined label `{}`", label)
+            }
+            ExecError::ValueOutOfRange { value } => write!(f, "{} is out of range", value),
+            ExecError::NotSleeping { ticks } => {
+                write!(f, "a part did not sleep within {} steps", ticks)
+            }
+            ExecError::CombinationalLoop => write!(f, "logic gates do not settle"),
+            ExecError::Line { line, error } => write!(f, "line {}: {}", line + 1, error),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetError {
+    NoSuchDevice {
+        device: DeviceId,
+    },
+    NoSuchNet {
+        net: NetId,
+    },
+    /// The device has no such pin.
+    NoSuchPin {
+        device: DeviceId,
+        pin: Pin,
+    },
+    /// The pin is a simple I/O pin on an XBus net or the other way around.
+    KindMismatch {
+        device: DeviceId,
+        pin: Pin,
+        kind: NetKind,
+    },
+    AlreadyConnected {
+        device: DeviceId,
+        pin: Pin,
+    },
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::NoSuchDevice { device } => write!(f, "no device {}", device),
+            NetError::NoSuchNet { net } => write!(f, "no net {}", net),
+            NetError::NoSuchPin { device, pin } => {
+                write!(f, "device {} has no pin {}", device, pin)
+            }
+            NetError::KindMismatch { device, pin, kind } => write!(
+                f,
+                "pin {} of device {} cannot join a {:?} net",
+                pin, device, kind
+            ),
+            NetError::AlreadyConnected { device, pin } => {
+                write!(f, "pin {} of device {} is already connected", pin, device)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SaveError {
+    /// A line outside of any field, or body text under a field that takes none.
+    Syntax {
+        line: usize,
+        text: String,
+    },
+    MissingField {
+        field: String,
+    },
+    InvalidNumber {
+        line: usize,
+        field: String,
+        value: String,
+    },
+    /// A `[type]` we have no device for.
+    UnsupportedChip {
+        kind: String,
+    },
+    /// The code of the `chip`th chip in the file does not parse.
+    Code {
+        chip: usize,
+        error: ParseError,
+    },
+    /// A `[traces]` cell that is neither `.` nor a hex digit.
+    InvalidTrace {
+        row: usize,
+        column: usize,
+        cell: char,
+    },
+    /// A trace joins pins that cannot share a net.
+    Wiring {
+        error: NetError,
+    },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Syntax { line, text } => {
+                write!(f, "line {}: unexpected `{}`", line + 1, text)
+            }
+            SaveError::MissingField { field } => write!(f, "missing [{}]", field),
+            SaveError::InvalidNumber { line, field, value } => write!(
+                f,
+                "line {}: [{}] should be a number, not `{}`",
+                line + 1,
+                field,
+                value
+            ),
+            SaveError::UnsupportedChip { kind } => write!(f, "unsupported chip `{}`", kind),
+            SaveError::Code { chip, error } => write!(f, "chip {}: {}", chip, error),
+            SaveError::InvalidTrace { row, column, cell } => write!(
+                f,
+                "trace row {}, column {}: unexpected `{}`",
+                row + 1,
+                column + 1,
+                cell
+            ),
+            SaveError::Wiring { error } => write!(f, "traces: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    Parse(ParseError),
+    Exec(ExecError),
+    Net(NetError),
+    Save(SaveError),
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error::Parse(err)
+    }
+}
+
+impl From<ExecError> for Error {
+    fn from(err: ExecError) -> Self {
+        Error::Exec(err)
+    }
+}
+
+impl From<NetError> for Error {
+    fn from(err: NetError) -> Self {
+        Error::Net(err)
+    }
+}
+
+impl From<SaveError> for Error {
+    fn from(err: SaveError) -> Self {
+        Error::Save(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(err) => err.fmt(f),
+            Error::Exec(err) => err.fmt(f),
+            Error::Net(err) => err.fmt(f),
+            Error::Save(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
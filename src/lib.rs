@@ -0,0 +1,10 @@
+pub mod board;
+pub mod code;
+pub mod device;
+pub mod devices;
+pub mod error;
+pub mod net;
+pub mod save;
+#[cfg(test)]
+mod testing;
+pub mod value;
@@ -1,8 +1,3 @@
-mod board;
-mod code;
-mod device;
-mod devices;
-
 fn main() {
     println!("Hello, world!");
 }
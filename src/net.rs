@@ -0,0 +1,81 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    code::Pin,
+    device::{Attachment, SimpleIOPin, SimpleIOWire, XBusPin, XBusWire},
+};
+
+pub type DeviceId = usize;
+pub type NetId = usize;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NetKind {
+    SimpleIO,
+    XBus,
+}
+
+impl NetKind {
+    pub fn of(pin: Pin) -> Self {
+        if pin.is_xbus() {
+            NetKind::XBus
+        } else {
+            NetKind::SimpleIO
+        }
+    }
+}
+
+enum Wire {
+    SimpleIO(Rc<RefCell<SimpleIOWire>>),
+    XBus(Rc<RefCell<XBusWire>>),
+}
+
+/// A trace on the board, joining device pins of a single kind.
+pub struct Net {
+    wire: Wire,
+    endpoints: Vec<(DeviceId, Pin)>,
+}
+
+impl Net {
+    pub fn new(kind: NetKind) -> Self {
+        Net {
+            wire: match kind {
+                NetKind::SimpleIO => Wire::SimpleIO(SimpleIOWire::new()),
+                NetKind::XBus => Wire::XBus(XBusWire::new()),
+            },
+            endpoints: Vec::new(),
+        }
+    }
+
+    pub fn kind(&self) -> NetKind {
+        match self.wire {
+            Wire::SimpleIO(_) => NetKind::SimpleIO,
+            Wire::XBus(_) => NetKind::XBus,
+        }
+    }
+
+    pub fn endpoints(&self) -> &[(DeviceId, Pin)] {
+        &self.endpoints
+    }
+
+    pub(crate) fn add_endpoint(&mut self, device: DeviceId, pin: Pin) {
+        self.endpoints.push((device, pin));
+    }
+
+    /// Empties the net of any values left over from a previous run.
+    pub fn clear(&self) {
+        match &self.wire {
+            Wire::SimpleIO(wire) => wire.borrow_mut().clear(),
+            Wire::XBus(wire) => wire.borrow_mut().clear(),
+        }
+    }
+
+    /// A fresh attachment onto the net. `Board::connect` hands these to
+    /// devices; on their own they are for watching or driving the net from
+    /// outside the board.
+    pub fn probe(&self) -> Attachment {
+        match &self.wire {
+            Wire::SimpleIO(wire) => Attachment::SimpleIO(SimpleIOPin::connect(wire)),
+            Wire::XBus(wire) => Attachment::XBus(XBusPin::connect(wire)),
+        }
+    }
+}
@@ -0,0 +1,671 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use crate::{
+    board::Board,
+    code::{Pin, Pin::*, Program},
+    device::Device,
+    devices::{
+        display::DT2415,
+        expander::DX300,
+        gate::{GateKind, LogicGate},
+        mc::ChipModel,
+        memory::Memory,
+    },
+    error::{ParseError, SaveError, Span},
+    net::NetKind,
+};
+
+/// The game indents every line of a chip's code by this much.
+const CODE_INDENT: &str = "  ";
+
+/// Each `[traces]` cell is `.` for no trace, or a hex digit made of these
+/// bits, one for every neighbour the trace runs on to.
+const TRACE_RIGHT: u8 = 1;
+const TRACE_DOWN: u8 = 2;
+const TRACE_LEFT: u8 = 4;
+const TRACE_UP: u8 = 8;
+
+/// Every direction a trace can run in: its bit, the bit the neighbour needs
+/// to run back, and the step to that neighbour.
+const TRACE_STEPS: [(u8, u8, i32, i32); 4] = [
+    (TRACE_RIGHT, TRACE_LEFT, 1, 0),
+    (TRACE_DOWN, TRACE_UP, 0, 1),
+    (TRACE_LEFT, TRACE_RIGHT, -1, 0),
+    (TRACE_UP, TRACE_DOWN, 0, -1),
+];
+
+/// Where a microcontroller's pins sit on the grid. Its body is `width` cells
+/// wide from `[x]`, with one pin per row from `[y]` down each side, in the
+/// order listed. A trace reaches a pin from the cell just outside the body,
+/// running into it.
+struct Footprint {
+    width: i32,
+    left: &'static [Pin],
+    right: &'static [Pin],
+}
+
+impl Footprint {
+    fn of(model: ChipModel) -> Self {
+        match model {
+            ChipModel::MC4000 => Footprint {
+                width: 3,
+                left: &[P0, P1],
+                right: &[X0, X1],
+            },
+            ChipModel::MC4000X => Footprint {
+                width: 3,
+                left: &[X0, X1],
+                right: &[X2, X3],
+            },
+            ChipModel::MC6000 => Footprint {
+                width: 4,
+                left: &[P0, P1, X0],
+                right: &[X1, X2, X3],
+            },
+        }
+    }
+}
+
+/// The `[traces]` grid, one bitmask of `TRACE_*` per cell.
+struct TraceGrid(Vec<Vec<u8>>);
+
+impl TraceGrid {
+    fn parse(rows: &[String]) -> Result<Self, SaveError> {
+        let cells = rows.iter().enumerate().map(|(row, text)| {
+            text.chars()
+                .enumerate()
+                .map(|(column, cell)| match cell {
+                    '.' => Ok(0),
+                    _ => cell
+                        .to_digit(16)
+                        .map(|bits| bits as u8)
+                        .ok_or(SaveError::InvalidTrace { row, column, cell }),
+                })
+                .collect()
+        });
+        Ok(TraceGrid(cells.collect::<Result<_, _>>()?))
+    }
+
+    /// The bits of the cell at `x`, `y`, with nothing off the grid.
+    fn cell(&self, x: i32, y: i32) -> u8 {
+        let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) else {
+            return 0;
+        };
+        self.0
+            .get(y)
+            .and_then(|row| row.get(x))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Numbers the separate traces on the grid, giving back the number of
+    /// the one running through each cell. Two cells only join if each runs
+    /// on to the other.
+    fn label(&self) -> HashMap<(i32, i32), usize> {
+        let mut labels = HashMap::new();
+        let mut count = 0;
+        for (y, row) in self.0.iter().enumerate() {
+            for x in 0..row.len() {
+                let start = (x as i32, y as i32);
+                if self.cell(start.0, start.1) == 0 || labels.contains_key(&start) {
+                    continue;
+                }
+                let label = count;
+                count += 1;
+                labels.insert(start, label);
+                let mut todo = vec![start];
+                while let Some((x, y)) = todo.pop() {
+                    for (bit, back, dx, dy) in TRACE_STEPS {
+                        let next = (x + dx, y + dy);
+                        if self.cell(x, y) & bit != 0
+                            && self.cell(next.0, next.1) & back != 0
+                            && !labels.contains_key(&next)
+                        {
+                            labels.insert(next, label);
+                            todo.push(next);
+                        }
+                    }
+                }
+            }
+        }
+        labels
+    }
+}
+
+/// A solution file as the game saves it: a few header fields, the trace grid,
+/// then one `[chip]` block per part on the board.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Solution {
+    pub name: String,
+    pub puzzle: String,
+    pub production_cost: u32,
+    /// Header fields we do not interpret, such as `[power-usage]`, in file
+    /// order.
+    pub extra: Vec<(String, String)>,
+    /// Rows of the `[traces]` grid, kept as written.
+    pub traces: Vec<String>,
+    pub chips: Vec<Chip>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Chip {
+    /// The part's `[type]`, e.g. `UC4000`.
+    pub kind: String,
+    pub x: i32,
+    pub y: i32,
+    /// Fields we do not interpret, such as `[is-puzzle-provided]`.
+    pub extra: Vec<(String, String)>,
+    pub code: Option<String>,
+}
+
+impl Chip {
+    /// The microcontroller this chip is. Save files still use the parts'
+    /// old `UC` names.
+    pub fn model(&self) -> Option<ChipModel> {
+        match self.kind.as_str() {
+            "UC4000" => Some(ChipModel::MC4000),
+            "UC4000X" => Some(ChipModel::MC4000X),
+            "UC6000" => Some(ChipModel::MC6000),
+            _ => None,
+        }
+    }
+
+    /// A fresh device for this part, the `idx`th in the file. A 100P-14
+    /// takes its contents from `[code]`, as numbers separated by spaces or
+    /// commas.
+    fn build(&self, idx: usize) -> Result<Box<dyn Device>, SaveError> {
+        if let Some(model) = self.model() {
+            return self
+                .program()
+                .and_then(|program| model.build(&program))
+                .map_err(|error| SaveError::Code { chip: idx, error });
+        }
+
+        Ok(match self.kind.as_str() {
+            "LC70G04" => Box::new(LogicGate::new(GateKind::Not)),
+            "LC70G08" => Box::new(LogicGate::new(GateKind::And)),
+            "LC70G32" => Box::new(LogicGate::new(GateKind::Or)),
+            "LC70G86" => Box::new(LogicGate::new(GateKind::Xor)),
+            "200P-33" => Box::new(Memory::ram()),
+            "100P-14" => Box::new(Memory::rom(
+                &self
+                    .rom_values()
+                    .map_err(|error| SaveError::Code { chip: idx, error })?,
+            )),
+            "DX300" => Box::new(DX300::new()),
+            "DT2415" => Box::new(DT2415::new()),
+            _ => {
+                return Err(SaveError::UnsupportedChip {
+                    kind: self.kind.clone(),
+                })
+            }
+        })
+    }
+
+    fn rom_values(&self) -> Result<Vec<i16>, ParseError> {
+        let mut values = Vec::new();
+        for (line, text) in self.code.as_deref().unwrap_or_default().lines().enumerate() {
+            let mut column = 0;
+            for word in text.split([' ', '\t', ',']) {
+                if !word.is_empty() {
+                    let val = word.parse().map_err(|_| ParseError::Syntax {
+                        span: Span { line, column },
+                        rest: text[column..].to_string(),
+                    })?;
+                    values.push(val);
+                }
+                column += word.len() + 1;
+            }
+        }
+        Ok(values)
+    }
+
+    /// Each pin a trace can reach, with the cell it is reached from and the
+    /// bit a trace there runs into it with. Only the microcontrollers have
+    /// their pins placed so far.
+    fn pin_cells(&self) -> Vec<(Pin, (i32, i32), u8)> {
+        let Some(model) = self.model() else {
+            return Vec::new();
+        };
+        let footprint = Footprint::of(model);
+        let left = footprint
+            .left
+            .iter()
+            .zip(self.y..)
+            .map(|(&pin, y)| (pin, (self.x - 1, y), TRACE_RIGHT));
+        let right = footprint
+            .right
+            .iter()
+            .zip(self.y..)
+            .map(|(&pin, y)| (pin, (self.x + footprint.width, y), TRACE_LEFT));
+        left.chain(right).collect()
+    }
+
+    pub fn program(&self) -> Result<Program, ParseError> {
+        Program::parse(self.code.as_deref().unwrap_or_default())
+    }
+
+    /// Replaces the chip's code with `program` in canonical layout.
+    pub fn set_program(&mut self, program: &Program) {
+        let text = program.to_string();
+        self.code = Some(text.strip_suffix('\n').unwrap_or(&text).to_string());
+    }
+}
+
+impl Solution {
+    /// Builds a board with one device per part, in file order, so device `i`
+    /// is `chips[i]`, and one net per trace that reaches a pin, in the order
+    /// the pins are first reached.
+    ///
+    /// Only the microcontrollers' pins are placed on the grid so far, see
+    /// `Footprint`; a trace running into any other part leaves it
+    /// unconnected.
+    pub fn to_board(&self) -> Result<Board, SaveError> {
+        let mut board = Board::new();
+        for (idx, chip) in self.chips.iter().enumerate() {
+            board.add_device(chip.build(idx)?);
+        }
+
+        let grid = TraceGrid::parse(&self.traces)?;
+        let labels = grid.label();
+        let mut nets = HashMap::new();
+        for (device, chip) in self.chips.iter().enumerate() {
+            for (pin, (x, y), into) in chip.pin_cells() {
+                let Some(label) = labels.get(&(x, y)) else {
+                    continue;
+                };
+                if grid.cell(x, y) & into == 0 {
+                    continue;
+                }
+                let net = *nets
+                    .entry(*label)
+                    .or_insert_with(|| board.add_net(NetKind::of(pin)));
+                board
+                    .connect(net, device, pin)
+                    .map_err(|error| SaveError::Wiring { error })?;
+            }
+        }
+        Ok(board)
+    }
+}
+
+/// One `[key] value` line and the lines under it.
+struct Field {
+    line: usize,
+    key: String,
+    value: String,
+    body: Vec<String>,
+}
+
+impl Field {
+    fn number<T: FromStr>(&self) -> Result<T, SaveError> {
+        self.value.parse().map_err(|_| SaveError::InvalidNumber {
+            line: self.line,
+            field: self.key.clone(),
+            value: self.value.clone(),
+        })
+    }
+}
+
+fn lex_fields(input: &str) -> Result<Vec<Field>, SaveError> {
+    let mut fields: Vec<Field> = Vec::new();
+
+    for (line, text) in input.lines().enumerate() {
+        if let Some(rest) = text.strip_prefix('[') {
+            let (key, value) = rest.split_once(']').ok_or_else(|| SaveError::Syntax {
+                line,
+                text: text.to_string(),
+            })?;
+            fields.push(Field {
+                line,
+                key: key.to_string(),
+                value: value.trim().to_string(),
+                body: Vec::new(),
+            });
+            continue;
+        }
+
+        // blank lines only matter inside a body, and are trimmed off its end
+        // once the whole file is read
+        match fields.last_mut() {
+            Some(field) if matches!(field.key.as_str(), "traces" | "code") => {
+                field.body.push(text.to_string());
+            }
+            _ if text.trim().is_empty() => {}
+            _ => {
+                return Err(SaveError::Syntax {
+                    line,
+                    text: text.to_string(),
+                })
+            }
+        }
+    }
+
+    for field in &mut fields {
+        while field.body.last().is_some_and(|line| line.trim().is_empty()) {
+            field.body.pop();
+        }
+    }
+    Ok(fields)
+}
+
+fn parse_chip(fields: &[Field]) -> Result<Chip, SaveError> {
+    let mut chip = Chip::default();
+    let (mut kind, mut x, mut y) = (None, None, None);
+
+    for field in fields {
+        match field.key.as_str() {
+            "type" => kind = Some(field.value.clone()),
+            "x" => x = Some(field.number()?),
+            "y" => y = Some(field.number()?),
+            "code" => {
+                let lines: Vec<&str> = field
+                    .body
+                    .iter()
+                    .map(|line| line.strip_prefix(CODE_INDENT).unwrap_or(line))
+                    .collect();
+                chip.code = Some(lines.join("\n"));
+            }
+            _ => chip.extra.push((field.key.clone(), field.value.clone())),
+        }
+    }
+
+    let missing = |field: &str| SaveError::MissingField {
+        field: field.to_string(),
+    };
+    chip.kind = kind.ok_or_else(|| missing("type"))?;
+    chip.x = x.ok_or_else(|| missing("x"))?;
+    chip.y = y.ok_or_else(|| missing("y"))?;
+    Ok(chip)
+}
+
+impl FromStr for Solution {
+    type Err = SaveError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = lex_fields(s)?;
+        let mut blocks = fields.split(|field| field.key == "chip");
+        let header = blocks.next().unwrap_or_default();
+
+        let mut solution = Solution::default();
+        let (mut name, mut puzzle, mut cost) = (None, None, None);
+        for field in header {
+            match field.key.as_str() {
+                "name" => name = Some(field.value.clone()),
+                "puzzle" => puzzle = Some(field.value.clone()),
+                "production-cost" => cost = Some(field.number()?),
+                "traces" => solution.traces = field.body.clone(),
+                _ => solution
+                    .extra
+                    .push((field.key.clone(), field.value.clone())),
+            }
+        }
+
+        let missing = |field: &str| SaveError::MissingField {
+            field: field.to_string(),
+        };
+        solution.name = name.ok_or_else(|| missing("name"))?;
+        solution.puzzle = puzzle.ok_or_else(|| missing("puzzle"))?;
+        solution.production_cost = cost.ok_or_else(|| missing("production-cost"))?;
+        solution.chips = blocks.map(parse_chip).collect::<Result<_, _>>()?;
+        Ok(solution)
+    }
+}
+
+impl fmt::Display for Solution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[name] {}", self.name)?;
+        writeln!(f, "[puzzle] {}", self.puzzle)?;
+        writeln!(f, "[production-cost] {}", self.production_cost)?;
+        for (key, value) in &self.extra {
+            writeln!(f, "[{}] {}", key, value)?;
+        }
+
+        if !self.traces.is_empty() {
+            writeln!(f, "\n[traces] ")?;
+            for row in &self.traces {
+                writeln!(f, "{}", row)?;
+            }
+        }
+
+        for chip in &self.chips {
+            writeln!(f, "\n[chip] ")?;
+            writeln!(f, "[type] {}", chip.kind)?;
+            writeln!(f, "[x] {}", chip.x)?;
+            writeln!(f, "[y] {}", chip.y)?;
+            for (key, value) in &chip.extra {
+                writeln!(f, "[{}] {}", key, value)?;
+            }
+            if let Some(code) = &chip.code {
+                writeln!(f, "[code] ")?;
+                for line in code.lines() {
+                    writeln!(f, "{}{}", CODE_INDENT, line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::NetError;
+
+    const SAVE: &str = "\
+[name] Fake Surveillance Camera
+[puzzle] Sz000
+[production-cost] 600
+[power-usage] 240
+[lines-of-code] 6
+
+[traces]
+......................
+....3555555...........
+....9...6.............
+........955...........
+.................14...
+
+[chip]
+[type] UC4000
+[x] 5
+[y] 2
+[code]
+  mov 0 p0
+  slp 6
+
+  mov 100 p0
+  slp 4
+
+[chip]
+[type] UC6000
+[x] 11
+[y] 1
+[is-puzzle-provided] false
+[code]
+  # just waits
+  slx x0
+";
+
+    #[test]
+    fn test_parse_save() {
+        let solution: Solution = SAVE.parse().unwrap();
+        assert_eq!(solution.name, "Fake Surveillance Camera");
+        assert_eq!(solution.puzzle, "Sz000");
+        assert_eq!(solution.production_cost, 600);
+        assert_eq!(
+            solution.extra,
+            vec![
+                (String::from("power-usage"), String::from("240")),
+                (String::from("lines-of-code"), String::from("6")),
+            ]
+        );
+        assert_eq!(solution.traces.len(), 5);
+
+        assert_eq!(solution.chips.len(), 2);
+        assert_eq!(solution.chips[0].model(), Some(ChipModel::MC4000));
+        assert_eq!((solution.chips[0].x, solution.chips[0].y), (5, 2));
+        assert_eq!(
+            solution.chips[0].code.as_deref(),
+            Some("mov 0 p0\nslp 6\n\nmov 100 p0\nslp 4")
+        );
+        assert_eq!(solution.chips[1].model(), Some(ChipModel::MC6000));
+        assert_eq!(
+            solution.chips[1].extra,
+            vec![(String::from("is-puzzle-provided"), String::from("false"))]
+        );
+    }
+
+    #[test]
+    fn test_save_round_trips() {
+        let solution: Solution = SAVE.parse().unwrap();
+        let written = solution.to_string();
+        assert_eq!(written.parse::<Solution>().unwrap(), solution);
+    }
+
+    #[test]
+    fn test_save_errors() {
+        assert_eq!(
+            "[name] a\n[puzzle] b".parse::<Solution>(),
+            Err(SaveError::MissingField {
+                field: String::from("production-cost")
+            })
+        );
+        assert_eq!(
+            "[name] a\n[puzzle] b\n[production-cost] lots".parse::<Solution>(),
+            Err(SaveError::InvalidNumber {
+                line: 2,
+                field: String::from("production-cost"),
+                value: String::from("lots"),
+            })
+        );
+        assert_eq!(
+            "[name] a\nstray".parse::<Solution>(),
+            Err(SaveError::Syntax {
+                line: 1,
+                text: String::from("stray"),
+            })
+        );
+    }
+
+    #[test]
+    fn test_save_to_board() {
+        let mut solution: Solution = SAVE.parse().unwrap();
+        let mut board = solution.to_board().unwrap();
+        board.advance().unwrap();
+        assert_eq!(board.state().seconds, 1);
+
+        solution.chips[1].code = Some(String::from("mov 1 dat\nbogus"));
+        assert!(matches!(
+            solution.to_board(),
+            Err(SaveError::Code { chip: 1, .. })
+        ));
+        solution.chips[1].kind = String::from("C2S-RF901");
+        assert_eq!(
+            solution.to_board().err(),
+            Some(SaveError::UnsupportedChip {
+                kind: String::from("C2S-RF901")
+            })
+        );
+    }
+
+    #[test]
+    fn test_save_traces_to_nets() {
+        let mut solution: Solution = SAVE.parse().unwrap();
+        let mut board = solution.to_board().unwrap();
+        // the trace into the MC4000's x1 never runs into the chip, and the
+        // one at the bottom right reaches no pin at all
+        let nets: Vec<_> = board
+            .nets()
+            .iter()
+            .map(|net| (net.kind(), net.endpoints().to_vec()))
+            .collect();
+        assert_eq!(
+            nets,
+            vec![
+                (NetKind::SimpleIO, vec![(0, P0), (1, P0)]),
+                (NetKind::XBus, vec![(0, X0), (1, X0)]),
+            ]
+        );
+        let probe = board.net(0).unwrap().probe();
+        board.advance().unwrap();
+        assert_eq!(probe.read_value_from_attachment(), Some(0));
+        for _ in 0..6 {
+            board.advance().unwrap();
+        }
+        assert_eq!(probe.read_value_from_attachment(), Some(100));
+
+        // run the XBus trace on into the MC6000's p1 as well
+        solution.traces[2].replace_range(10..11, "3");
+        solution.traces[3].replace_range(10..11, "D");
+        assert_eq!(
+            solution.to_board().err(),
+            Some(SaveError::Wiring {
+                error: NetError::KindMismatch {
+                    device: 1,
+                    pin: P1,
+                    kind: NetKind::XBus
+                }
+            })
+        );
+
+        solution.traces[0].replace_range(3..4, "x");
+        assert_eq!(
+            solution.to_board().err(),
+            Some(SaveError::InvalidTrace {
+                row: 0,
+                column: 3,
+                cell: 'x'
+            })
+        );
+    }
+
+    #[test]
+    fn test_save_parts_to_board() {
+        let mut solution: Solution = SAVE.parse().unwrap();
+        for kind in ["LC70G04", "LC70G86", "200P-33", "DX300", "DT2415"] {
+            solution.chips.push(Chip {
+                kind: String::from(kind),
+                ..Chip::default()
+            });
+        }
+        solution.chips.push(Chip {
+            kind: String::from("100P-14"),
+            code: Some(String::from("5, 10\n15 20")),
+            ..Chip::default()
+        });
+        let mut board = solution.to_board().unwrap();
+        board.advance().unwrap();
+
+        solution.chips.last_mut().unwrap().code = Some(String::from("5 x"));
+        assert_eq!(
+            solution.to_board().err(),
+            Some(SaveError::Code {
+                chip: 7,
+                error: ParseError::Syntax {
+                    span: Span { line: 0, column: 2 },
+                    rest: String::from("x"),
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_set_program() {
+        let mut chip = Chip::default();
+        let program = Program::parse("top: mov 1 acc\n+ jmp top").unwrap();
+        chip.set_program(&program);
+        assert_eq!(
+            chip.code.as_deref(),
+            Some("top:   mov 1 acc\n     + jmp top")
+        );
+        assert_eq!(chip.program().unwrap(), program);
+
+        // a line as long as the editor allows is saved in a form that
+        // still parses
+        let program = Program::parse("mov 1 acc#abcdefghi").unwrap();
+        chip.set_program(&program);
+        assert_eq!(chip.code.as_deref(), Some("mov 1 acc#abcdefghi"));
+        assert_eq!(chip.program().unwrap(), program);
+    }
+}
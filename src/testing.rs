@@ -0,0 +1,44 @@
+//! Fixtures shared by the tests of the board and its parts.
+
+use crate::{
+    board::Board,
+    code::{Pin, Program},
+    device::Device,
+    devices::mc::ChipModel,
+    net::{DeviceId, NetId, NetKind},
+};
+
+/// Adds `device` to `board` with each pin wired into its net.
+pub fn add_part(board: &mut Board, device: Box<dyn Device>, pins: &[(Pin, NetId)]) -> DeviceId {
+    let device = board.add_device(device);
+    for &(pin, net) in pins {
+        board.connect(net, device, pin).unwrap();
+    }
+    device
+}
+
+/// Adds `device` to `board` with each of `pins` wired into a fresh net of
+/// its own, returning the nets in the same order.
+pub fn add_part_on_new_nets<const N: usize>(
+    board: &mut Board,
+    device: Box<dyn Device>,
+    pins: [Pin; N],
+) -> [NetId; N] {
+    let device = board.add_device(device);
+    pins.map(|pin| {
+        let net = board.add_net(NetKind::of(pin));
+        board.connect(net, device, pin).unwrap();
+        net
+    })
+}
+
+/// Adds a `model` chip running `code`, wired up like `add_part`.
+pub fn add_chip(
+    board: &mut Board,
+    model: ChipModel,
+    code: &str,
+    pins: &[(Pin, NetId)],
+) -> DeviceId {
+    let program = Program::parse(code).unwrap();
+    add_part(board, model.build(&program).unwrap(), pins)
+}
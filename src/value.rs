@@ -0,0 +1,42 @@
+//! Value ranges of the game. Registers and XBus values saturate to
+//! -999..=999, simple I/O pins to 0..=100. Arithmetic is done in `i32` and
+//! clamped on the way back into a register, so it can never overflow.
+
+pub const MIN: i16 = -999;
+pub const MAX: i16 = 999;
+
+pub const SIMPLE_IO_MIN: i16 = 0;
+pub const SIMPLE_IO_MAX: i16 = 100;
+/// Level at or above which a simple I/O pin reads as on, for the parts that
+/// only care whether it is.
+pub const SIMPLE_IO_THRESHOLD: i16 = 50;
+
+pub fn clamp(val: i32) -> i16 {
+    val.clamp(MIN.into(), MAX.into()) as i16
+}
+
+pub fn clamp_simple_io(val: i32) -> i16 {
+    val.clamp(SIMPLE_IO_MIN.into(), SIMPLE_IO_MAX.into()) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp() {
+        assert_eq!(clamp(0), 0);
+        assert_eq!(clamp(-999), -999);
+        assert_eq!(clamp(999), 999);
+        assert_eq!(clamp(1000), 999);
+        assert_eq!(clamp(999 * 999), 999);
+        assert_eq!(clamp(-999 * 999), -999);
+    }
+
+    #[test]
+    fn test_clamp_simple_io() {
+        assert_eq!(clamp_simple_io(50), 50);
+        assert_eq!(clamp_simple_io(-5), 0);
+        assert_eq!(clamp_simple_io(101), 100);
+    }
+}